@@ -0,0 +1,430 @@
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::colors::*;
+use crate::fov::in_sight;
+use crate::map::{MAP_HEIGHT, MAP_WIDTH};
+use crate::object::{move_astar, move_by, mut_two, Object};
+use crate::path::{find_path, step_cost};
+use crate::ranged::{line_of_fire, ranged_attack};
+use crate::scheduler::MOVE_COST;
+use crate::status::StatusKind;
+use crate::{Game, BLIND_RADIUS, PLAYER};
+
+// a sleeping monster this close to a player it can see wakes up for sure;
+// further away it only has a chance of noticing them each turn
+const WAKE_DISTANCE: f32 = 3.0;
+const NOTICE_CHANCE: u32 = 4; // 1 in ...
+// wanderers pick somewhere to go at most this far away
+const WANDER_RANGE: i32 = 10;
+// monsters run away below this fraction of their max HP
+const FLEE_BELOW: f32 = 0.25;
+// for this many turns, then come back to fight to the death
+const FLEE_TURNS: i32 = 10;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Ai {
+    // until the player comes close or a fight breaks out nearby
+    Asleep,
+    // roaming the level, towards a spot picked at random
+    Wandering {
+        destination: Option<(i32, i32)>,
+    },
+    // after the player; when they're out of sight, to where they were last
+    // seen. once it has run away it won't again
+    Hunting {
+        last_known: (i32, i32),
+        fled: bool,
+    },
+    // hurt badly, and getting away from the player for a while
+    Fleeing {
+        num_turns: i32,
+    },
+}
+
+// Where fighting broke out, and how far away it can be heard.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Noise {
+    pub position: (i32, i32),
+    pub loudness: i32,
+    // how many turns have gone by since
+    pub age: i32,
+}
+
+// the monster's turn, however it spends it. doing nothing much (moving,
+// waiting, sleeping) costs as much as a move; attacks charge for themselves
+pub fn ai_take_turn(monster_id: usize, game: &mut Game, objects: &mut [Object]) {
+    let energy = objects[monster_id].energy;
+    ai_act(monster_id, game, objects);
+    if objects[monster_id].energy == energy {
+        objects[monster_id].spend_energy(MOVE_COST);
+    }
+}
+
+fn ai_act(monster_id: usize, game: &mut Game, objects: &mut [Object]) {
+    use Ai::*;
+    if let Some(ranged) = objects[monster_id].ranged.as_mut() {
+        ranged.recharge = (ranged.recharge - 1).max(0);
+    }
+    if objects[monster_id].has_status(StatusKind::Stunned) {
+        // loses its turn
+        return;
+    }
+    if objects[monster_id].has_status(StatusKind::Confused) {
+        // move randomly, whatever it had in mind
+        move_by(
+            monster_id,
+            game.rng.gen_range(-1, 2),
+            game.rng.gen_range(-1, 2),
+            &mut game.map,
+            objects,
+        );
+        return;
+    }
+    if let Some(ai) = objects[monster_id].ai.take() {
+        let new_ai = match ai {
+            Asleep => ai_asleep(monster_id, game, objects),
+            Wandering { destination } => ai_wander(monster_id, game, objects, destination),
+            Hunting { last_known, fled } => ai_hunt(monster_id, game, objects, last_known, fled),
+            Fleeing { num_turns } => ai_flee(monster_id, game, objects, num_turns),
+        };
+        objects[monster_id].ai = Some(new_ai);
+    }
+}
+
+// whether the monster can see the player, looking out from where it stands
+// as far as its perception goes. the player's torch may well reach further
+fn sees_player(monster_id: usize, game: &Game, objects: &[Object]) -> bool {
+    let monster = &objects[monster_id];
+    let player = &objects[PLAYER];
+    let perception = if monster.has_status(StatusKind::Blinded) {
+        BLIND_RADIUS
+    } else {
+        monster.perception
+    };
+    if !player.alive || monster.distance_to(player) > perception as f32 {
+        return false;
+    }
+    in_sight(&game.map, monster.pos(), player.pos())
+}
+
+// the closest noise made lately that the monster is within earshot of
+fn heard_noise(monster_id: usize, game: &Game, objects: &[Object]) -> Option<(i32, i32)> {
+    let monster = &objects[monster_id];
+    game.noises
+        .iter()
+        .map(|noise| (noise.position, noise.loudness))
+        .filter(|&((x, y), loudness)| monster.distance(x, y) <= loudness as f32)
+        .min_by_key(|&((x, y), _)| (x - monster.x).pow(2) + (y - monster.y).pow(2))
+        .map(|(position, _)| position)
+}
+
+fn badly_hurt(monster_id: usize, game: &Game, objects: &[Object]) -> bool {
+    let monster = &objects[monster_id];
+    let hp = monster.fighter.map_or(0, |f| f.hp);
+    (hp as f32) < FLEE_BELOW * monster.max_hp(game) as f32
+}
+
+fn ai_asleep(monster_id: usize, game: &mut Game, objects: &mut [Object]) -> Ai {
+    if sees_player(monster_id, game, objects) {
+        let close = objects[monster_id].distance_to(&objects[PLAYER]) <= WAKE_DISTANCE;
+        if close || game.rng.gen_weighted_bool(NOTICE_CHANCE) {
+            return Ai::Hunting {
+                last_known: objects[PLAYER].pos(),
+                fled: false,
+            };
+        }
+    }
+    // woken by a fight: go and have a look
+    match heard_noise(monster_id, game, objects) {
+        Some(noise) => Ai::Hunting {
+            last_known: noise,
+            fled: false,
+        },
+        None => Ai::Asleep,
+    }
+}
+
+fn ai_wander(
+    monster_id: usize,
+    game: &mut Game,
+    objects: &mut [Object],
+    destination: Option<(i32, i32)>,
+) -> Ai {
+    if sees_player(monster_id, game, objects) {
+        let player = objects[PLAYER].pos();
+        return ai_hunt(monster_id, game, objects, player, false);
+    }
+    if let Some(noise) = heard_noise(monster_id, game, objects) {
+        return Ai::Hunting {
+            last_known: noise,
+            fled: false,
+        };
+    }
+
+    let (monster_x, monster_y) = objects[monster_id].pos();
+    let destination = match destination {
+        Some(destination) if destination != (monster_x, monster_y) => destination,
+        // got there (or just started): look for somewhere new, for next turn
+        _ => {
+            let x = game
+                .rng
+                .gen_range(monster_x - WANDER_RANGE, monster_x + WANDER_RANGE + 1)
+                .clamp(0, MAP_WIDTH - 1);
+            let y = game
+                .rng
+                .gen_range(monster_y - WANDER_RANGE, monster_y + WANDER_RANGE + 1)
+                .clamp(0, MAP_HEIGHT - 1);
+            let destination = step_cost(&game.map, x, y).map(|_| (x, y));
+            return Ai::Wandering { destination };
+        }
+    };
+    match find_path((monster_x, monster_y), destination, &game.map, objects) {
+        Some(path) => {
+            let (next_x, next_y) = path[0];
+            move_by(
+                monster_id,
+                next_x - monster_x,
+                next_y - monster_y,
+                &mut game.map,
+                objects,
+            );
+            Ai::Wandering {
+                destination: Some(destination),
+            }
+        }
+        // no way there (or someone's standing on it): pick another spot
+        None => Ai::Wandering { destination: None },
+    }
+}
+
+fn ai_hunt(
+    monster_id: usize,
+    game: &mut Game,
+    objects: &mut [Object],
+    last_known: (i32, i32),
+    fled: bool,
+) -> Ai {
+    if !fled && badly_hurt(monster_id, game, objects) {
+        game.messages.add(
+            format!("The {} turns to flee!", objects[monster_id].name),
+            LIGHT_YELLOW,
+        );
+        return ai_flee(monster_id, game, objects, FLEE_TURNS);
+    }
+
+    let (monster_x, monster_y) = objects[monster_id].pos();
+    if !sees_player(monster_id, game, objects) {
+        // lost them: look where they were last seen, then give up
+        if (monster_x, monster_y) == last_known {
+            return Ai::Wandering { destination: None };
+        }
+        let (x, y) = last_known;
+        move_astar(monster_id, x, y, &mut game.map, objects);
+        return Ai::Hunting { last_known, fled };
+    }
+
+    let (player_x, player_y) = objects[PLAYER].pos();
+    if ai_ranged(monster_id, game, objects) {
+        // shot at the player, or kept its distance
+    } else if objects[monster_id].distance_to(&objects[PLAYER]) >= 2.0 {
+        // move twd player if far, downhill on the map every monster shares
+        let chase = game.player_maps.chase(&game.map, (player_x, player_y));
+        match chase.step((monster_x, monster_y), objects) {
+            Some((dx, dy)) => move_by(monster_id, dx, dy, &mut game.map, objects),
+            // other monsters are in the way: look for a way round them
+            None => move_astar(monster_id, player_x, player_y, &mut game.map, objects),
+        }
+    } else if objects[PLAYER].fighter.is_some_and(|f| f.hp > 0) {
+        // close to player, attack if player is alive
+        let (monster, player) = mut_two(monster_id, PLAYER, objects);
+        monster.attack(player, game);
+    }
+    Ai::Hunting {
+        last_known: (player_x, player_y),
+        fled,
+    }
+}
+
+// monsters that can attack from a distance do, whenever the player is in
+// range and in the line of fire, and back away when they come too close.
+// false if there's nothing left to shoot with, nowhere to back away to, or
+// the player is right there while it waits for the next shot, and it's down
+// to chasing and bumping like everyone else
+fn ai_ranged(monster_id: usize, game: &mut Game, objects: &mut [Object]) -> bool {
+    let ranged = match &objects[monster_id].ranged {
+        Some(ranged) if ranged.has_ammo() => ranged.clone(),
+        _ => return false,
+    };
+    let monster = objects[monster_id].pos();
+    let player = objects[PLAYER].pos();
+    let distance = objects[monster_id].distance_to(&objects[PLAYER]);
+    let in_line =
+        distance <= ranged.range as f32 && line_of_fire(monster, player, &game.map, objects);
+    if ranged.ready() && in_line {
+        ranged_attack(monster_id, PLAYER, game, objects);
+        return true;
+    }
+    if distance < ranged.preferred_distance as f32 {
+        let flee = game.player_maps.flee(&game.map, player);
+        return match flee.step(monster, objects) {
+            Some((dx, dy)) => {
+                move_by(monster_id, dx, dy, &mut game.map, objects);
+                true
+            }
+            None => false,
+        };
+    }
+    // far enough away: wait there for the next shot, if there's a clear one.
+    // right next to the player there's no waiting, it's a fight
+    in_line && distance >= 2.0
+}
+
+fn ai_flee(monster_id: usize, game: &mut Game, objects: &mut [Object], num_turns: i32) -> Ai {
+    let player = objects[PLAYER].pos();
+    if num_turns <= 0 {
+        // nowhere left to run: it knows where the player is, it ran from them
+        return Ai::Hunting {
+            last_known: player,
+            fled: true,
+        };
+    }
+    if !sees_player(monster_id, game, objects) {
+        // hiding, for now
+        return Ai::Fleeing {
+            num_turns: num_turns - 1,
+        };
+    }
+    let monster = objects[monster_id].pos();
+    let flee = game.player_maps.flee(&game.map, player);
+    match flee.step(monster, objects) {
+        Some((dx, dy)) => move_by(monster_id, dx, dy, &mut game.map, objects),
+        // cornered: fight back
+        None if objects[monster_id].distance_to(&objects[PLAYER]) < 2.0 => {
+            let (monster, player) = mut_two(monster_id, PLAYER, objects);
+            monster.attack(player, game);
+        }
+        None => {}
+    }
+    Ai::Fleeing {
+        num_turns: num_turns - 1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::{make_monster, Map, Tile};
+    use crate::new_game;
+
+    // a room from (1, 1) to (20, 10), maybe with a wall across it at x = 10
+    fn room(divided: bool) -> Map {
+        let mut map = vec![vec![Tile::wall(); MAP_HEIGHT as usize]; MAP_WIDTH as usize];
+        for column in &mut map[1..=20] {
+            column[1..=10].fill(Tile::empty());
+        }
+        if divided {
+            map[10][1..=10].fill(Tile::wall());
+        }
+        map
+    }
+
+    // the player at (2, 2) and an orc in the given state
+    fn orc_at(x: i32, y: i32, ai: Ai, map: Map) -> (Game, Vec<Object>) {
+        let (mut game, mut objects) = new_game(1);
+        objects.truncate(1);
+        objects[PLAYER].set_pos(2, 2);
+        let mut orc = make_monster("Orc", x, y);
+        orc.ai = Some(ai);
+        objects.push(orc);
+        game.map = map;
+        (game, objects)
+    }
+
+    #[test]
+    fn sleepers_wake_up_when_the_player_comes_close() {
+        let (mut game, mut objects) = orc_at(4, 2, Ai::Asleep, room(false));
+        ai_act(1, &mut game, &mut objects);
+        assert_eq!(
+            objects[1].ai,
+            Some(Ai::Hunting {
+                last_known: (2, 2),
+                fled: false
+            })
+        );
+    }
+
+    #[test]
+    fn sleepers_sleep_on_behind_a_wall() {
+        let (mut game, mut objects) = orc_at(12, 2, Ai::Asleep, room(true));
+        for _ in 0..20 {
+            ai_act(1, &mut game, &mut objects);
+        }
+        assert_eq!(objects[1].ai, Some(Ai::Asleep));
+    }
+
+    #[test]
+    fn a_fight_nearby_wakes_sleepers_up() {
+        let (mut game, mut objects) = orc_at(12, 2, Ai::Asleep, room(true));
+        game.noises.push(Noise {
+            position: (15, 8),
+            loudness: 10,
+            age: 0,
+        });
+        ai_act(1, &mut game, &mut objects);
+        assert_eq!(
+            objects[1].ai,
+            Some(Ai::Hunting {
+                last_known: (15, 8),
+                fled: false
+            })
+        );
+    }
+
+    #[test]
+    fn hunters_look_where_the_player_was_last_seen_then_wander_off() {
+        let last_known = (18, 9);
+        let hunting = Ai::Hunting {
+            last_known,
+            fled: false,
+        };
+        let (mut game, mut objects) = orc_at(12, 2, hunting, room(true));
+        let mut turns = 0;
+        while matches!(objects[1].ai, Some(Ai::Hunting { .. })) && turns < 20 {
+            ai_act(1, &mut game, &mut objects);
+            turns += 1;
+        }
+        assert_eq!(objects[1].pos(), last_known);
+        assert!(matches!(objects[1].ai, Some(Ai::Wandering { .. })));
+    }
+
+    #[test]
+    fn the_badly_hurt_run_away_then_come_back_for_good() {
+        let hunting = Ai::Hunting {
+            last_known: (2, 2),
+            fled: false,
+        };
+        let (mut game, mut objects) = orc_at(4, 2, hunting, room(false));
+        objects[1].fighter.as_mut().unwrap().hp = 1;
+        ai_act(1, &mut game, &mut objects);
+        assert_eq!(
+            objects[1].ai,
+            Some(Ai::Fleeing {
+                num_turns: FLEE_TURNS - 1
+            })
+        );
+        assert!(objects[1].distance_to(&objects[PLAYER]) > 2.0);
+        for _ in 0..FLEE_TURNS {
+            ai_act(1, &mut game, &mut objects);
+        }
+        assert!(matches!(
+            objects[1].ai,
+            Some(Ai::Hunting { fled: true, .. })
+        ));
+        // and doesn't run a second time
+        for _ in 0..5 {
+            ai_act(1, &mut game, &mut objects);
+            assert!(matches!(objects[1].ai, Some(Ai::Hunting { .. })));
+        }
+    }
+}
@@ -0,0 +1,347 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
+use std::thread;
+
+use crate::ai::Ai;
+use crate::item::Item;
+use crate::map::{Door, MAP_HEIGHT, MAP_WIDTH};
+use crate::object::{Fighter, Object};
+use crate::{new_game, play_command, Command, Game, Ui, LEVEL_UP_BASE, LEVEL_UP_FACTOR, PLAYER};
+
+// How the scripted player behaves.
+#[derive(Clone, Copy, Debug)]
+pub struct BotConfig {
+    // drink a healing potion when HP drops below this fraction of max HP
+    pub heal_below: f32,
+    // give up on a game after this many commands
+    pub max_turns: u32,
+}
+
+impl Default for BotConfig {
+    fn default() -> Self {
+        BotConfig {
+            heal_below: 0.5,
+            max_turns: 10_000,
+        }
+    }
+}
+
+// How one bot game went.
+#[derive(Clone, Debug)]
+pub struct GameReport {
+    pub seed: u64,
+    pub died: bool,
+    pub dungeon_level: u32,
+    pub player_level: i32,
+    pub cause_of_death: Option<String>,
+    // XP earned on each dungeon level, starting at level 1
+    pub xp_per_level: Vec<i32>,
+    pub turns: u32,
+}
+
+// Plays a whole game with a fixed strategy: heal when hurt, walk to the
+// nearest monster and fight it, pick up whatever is on the level, then take
+// the stairs. It knows the whole map, it doesn't have to explore.
+struct Bot {
+    config: BotConfig,
+    level_ups: usize,
+}
+
+impl Ui for Bot {
+    fn target_tile(
+        &mut self,
+        _game: &Game,
+        _objects: &[Object],
+        _max_range: Option<f32>,
+    ) -> Option<(i32, i32)> {
+        // the bot never reads targeted scrolls, so it's never asked
+        None
+    }
+
+    fn level_up_choice(&mut self, _fighter: &Fighter) -> usize {
+        // spread the gifts evenly over HP, ATK and DEF
+        self.level_ups += 1;
+        (self.level_ups - 1) % 3
+    }
+}
+
+impl Bot {
+    fn choose_command(&self, game: &Game, objects: &[Object]) -> Command {
+        let player = &objects[PLAYER];
+
+        // drink a potion when hurt
+        let hp = player.fighter.map_or(0, |f| f.hp);
+        if (hp as f32) < self.config.heal_below * player.max_hp(game) as f32 {
+            let potion = game
+                .inventory
+                .iter()
+                .position(|item| matches!(item.item, Some(Item::Heal { .. })));
+            if let Some(potion) = potion {
+                return Command::Use(potion);
+            }
+        }
+
+        // grab anything lying underfoot
+        let item_underfoot = objects
+            .iter()
+            .any(|object| object.pos() == player.pos() && object.item.is_some());
+        if item_underfoot && game.inventory.len() < 26 {
+            return Command::PickUp;
+        }
+
+        let paths = Paths::from(game, objects, player.pos());
+
+        // fight the nearest monster, then collect items, then go down. ones
+        // running away are as fast as the bot, so it lets them go
+        let monsters = objects
+            .iter()
+            .enumerate()
+            .filter(|&(id, object)| id != PLAYER && object.fighter.is_some() && object.ai.is_some())
+            .filter(|&(_, object)| !matches!(object.ai, Some(Ai::Fleeing { .. })))
+            .map(|(_, object)| object.pos());
+        if let Some(step) = paths.step_to_nearest(monsters) {
+            return Command::Move(step.0, step.1);
+        }
+        let items = objects
+            .iter()
+            .filter(|object| object.item.is_some())
+            .map(|object| object.pos());
+        if game.inventory.len() < 26 {
+            if let Some(step) = paths.step_to_nearest(items) {
+                return Command::Move(step.0, step.1);
+            }
+        }
+        let stairs = objects
+            .iter()
+            .filter(|object| object.name == "stairs")
+            .map(|object| object.pos());
+        if objects
+            .iter()
+            .any(|object| object.name == "stairs" && object.pos() == player.pos())
+        {
+            return Command::Descend;
+        }
+        match paths.step_to_nearest(stairs) {
+            Some(step) => Command::Move(step.0, step.1),
+            None => Command::Wait,
+        }
+    }
+}
+
+// Breadth-first search from the player over the walkable tiles. Blocking
+// objects (monsters) are reached but not walked through, so they can still be
+// chosen as a destination.
+struct Paths {
+    start: (i32, i32),
+    parent: Vec<Option<(i32, i32)>>,
+    distance: Vec<Option<u32>>,
+}
+
+impl Paths {
+    fn from(game: &Game, objects: &[Object], start: (i32, i32)) -> Self {
+        let size = (MAP_WIDTH * MAP_HEIGHT) as usize;
+        let mut paths = Paths {
+            start,
+            parent: vec![None; size],
+            distance: vec![None; size],
+        };
+        // doors get opened by walking into them, locked ones only with a key
+        let has_key = game.inventory.iter().any(|item| item.item == Some(Item::Key));
+        let passable = |(x, y): (i32, i32)| {
+            let tile = &game.map[x as usize][y as usize];
+            match tile.door {
+                Some(Door::Locked) => has_key,
+                Some(_) => true,
+                None => !tile.blocked,
+            }
+        };
+        let mut occupied = vec![false; size];
+        for object in objects.iter().filter(|object| object.blocks) {
+            occupied[index(object.pos())] = true;
+        }
+        paths.distance[index(start)] = Some(0);
+        let mut queue = VecDeque::from(vec![start]);
+        while let Some((x, y)) = queue.pop_front() {
+            let distance = paths.distance[index((x, y))].unwrap();
+            for dx in -1..=1 {
+                for dy in -1..=1 {
+                    let next = (x + dx, y + dy);
+                    if next.0 < 0
+                        || next.1 < 0
+                        || next.0 >= MAP_WIDTH
+                        || next.1 >= MAP_HEIGHT
+                        || paths.distance[index(next)].is_some()
+                        || !passable(next)
+                    {
+                        continue;
+                    }
+                    paths.distance[index(next)] = Some(distance + 1);
+                    paths.parent[index(next)] = Some((x, y));
+                    if !occupied[index(next)] {
+                        queue.push_back(next);
+                    }
+                }
+            }
+        }
+        paths
+    }
+
+    // the first step (dx, dy) towards whichever of the goals is closest
+    fn step_to_nearest(&self, goals: impl Iterator<Item = (i32, i32)>) -> Option<(i32, i32)> {
+        let goal = goals
+            .filter(|&goal| goal != self.start)
+            .filter_map(|goal| self.distance[index(goal)].map(|distance| (distance, goal)))
+            .min()?
+            .1;
+        let mut step = goal;
+        while let Some(parent) = self.parent[index(step)] {
+            if parent == self.start {
+                break;
+            }
+            step = parent;
+        }
+        Some((step.0 - self.start.0, step.1 - self.start.1))
+    }
+}
+
+fn index((x, y): (i32, i32)) -> usize {
+    (x + y * MAP_WIDTH) as usize
+}
+
+// all the XP the player has earned, including what was spent on levelling up
+fn total_xp(player: &Object) -> i32 {
+    let spent: i32 = (1..player.level)
+        .map(|level| LEVEL_UP_BASE + level * LEVEL_UP_FACTOR)
+        .sum();
+    player.fighter.map_or(0, |f| f.xp) + spent
+}
+
+pub fn play_bot_game(seed: u64, config: BotConfig) -> GameReport {
+    let (mut game, mut objects) = new_game(seed);
+    let mut bot = Bot {
+        config,
+        level_ups: 0,
+    };
+    let mut xp_per_level = vec![];
+    let mut turns = 0;
+
+    while objects[PLAYER].alive && turns < config.max_turns {
+        let level = game.dungeon_level as usize;
+        let xp = total_xp(&objects[PLAYER]);
+
+        let command = bot.choose_command(&game, &objects);
+        play_command(command, &mut bot, &mut game, &mut objects);
+        turns += 1;
+
+        if xp_per_level.len() < level {
+            xp_per_level.resize(level, 0);
+        }
+        xp_per_level[level - 1] += total_xp(&objects[PLAYER]) - xp;
+    }
+
+    GameReport {
+        seed,
+        died: !objects[PLAYER].alive,
+        dungeon_level: game.dungeon_level,
+        player_level: objects[PLAYER].level,
+        cause_of_death: game.cause_of_death.clone(),
+        xp_per_level,
+        turns,
+    }
+}
+
+// The combined results of many bot games.
+#[derive(Clone, Debug, Default)]
+pub struct BotStats {
+    pub games: u32,
+    pub survivors: u32,
+    pub deaths_by_level: BTreeMap<u32, u32>,
+    pub causes_of_death: BTreeMap<String, u32>,
+    // total XP earned on each dungeon level, and how many games got there
+    pub xp_by_level: BTreeMap<u32, (i64, u32)>,
+    pub player_levels: BTreeMap<i32, u32>,
+}
+
+impl BotStats {
+    pub fn add(&mut self, report: &GameReport) {
+        self.games += 1;
+        if report.died {
+            *self.deaths_by_level.entry(report.dungeon_level).or_insert(0) += 1;
+            let cause = report.cause_of_death.clone().unwrap_or_else(|| "unknown".into());
+            *self.causes_of_death.entry(cause).or_insert(0) += 1;
+        } else {
+            self.survivors += 1;
+        }
+        for (level, &xp) in report.xp_per_level.iter().enumerate() {
+            let entry = self.xp_by_level.entry(level as u32 + 1).or_insert((0, 0));
+            entry.0 += xp as i64;
+            entry.1 += 1;
+        }
+        *self.player_levels.entry(report.player_level).or_insert(0) += 1;
+    }
+}
+
+impl fmt::Display for BotStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let percent = |count: u32| 100.0 * count as f32 / self.games.max(1) as f32;
+        writeln!(f, "{} games", self.games)?;
+        writeln!(f, "\ndeaths by dungeon level:")?;
+        for (level, &count) in &self.deaths_by_level {
+            writeln!(f, "  {:>3}: {:>6} ({:5.1}%)", level, count, percent(count))?;
+        }
+        writeln!(
+            f,
+            "  still alive at the turn limit: {} ({:.1}%)",
+            self.survivors,
+            percent(self.survivors)
+        )?;
+        writeln!(f, "\ncauses of death:")?;
+        for (cause, &count) in &self.causes_of_death {
+            writeln!(f, "  {:<20} {:>6} ({:5.1}%)", cause, count, percent(count))?;
+        }
+        writeln!(f, "\naverage XP earned per dungeon level:")?;
+        for (level, &(xp, games)) in &self.xp_by_level {
+            writeln!(
+                f,
+                "  {:>3}: {:>8.1} ({} games got there)",
+                level,
+                xp as f64 / games as f64,
+                games
+            )?;
+        }
+        writeln!(f, "\nfinal player levels:")?;
+        for (level, &count) in &self.player_levels {
+            writeln!(f, "  {:>3}: {:>6} ({:5.1}%)", level, count, percent(count))?;
+        }
+        Ok(())
+    }
+}
+
+// play `games` games, with consecutive seeds from `first_seed`, spread over
+// all the CPUs. the games don't share anything, so the results are the same
+// however many threads there are
+pub fn run_bot_games(first_seed: u64, games: u32, config: BotConfig) -> BotStats {
+    let threads = thread::available_parallelism().map_or(1, |n| n.get() as u32);
+    let reports: Vec<GameReport> = thread::scope(|scope| {
+        let handles: Vec<_> = (0..threads)
+            .map(|thread| {
+                scope.spawn(move || {
+                    (thread..games)
+                        .step_by(threads as usize)
+                        .map(|game| play_bot_game(first_seed.wrapping_add(game as u64), config))
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().unwrap())
+            .collect()
+    });
+
+    let mut stats = BotStats::default();
+    for report in &reports {
+        stats.add(report);
+    }
+    stats
+}
@@ -0,0 +1,38 @@
+use serde::{Deserialize, Serialize};
+
+// An RGB color. Kept separate from tcod's so the game logic can run without a
+// console; the frontend converts it when drawing.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Color {
+    pub const fn new(r: u8, g: u8, b: u8) -> Self {
+        Color { r, g, b }
+    }
+}
+
+// the libtcod palette entries used by the game
+pub const BLACK: Color = Color::new(0, 0, 0);
+pub const WHITE: Color = Color::new(255, 255, 255);
+pub const RED: Color = Color::new(255, 0, 0);
+pub const DARK_RED: Color = Color::new(191, 0, 0);
+pub const LIGHT_RED: Color = Color::new(255, 63, 63);
+pub const ORANGE: Color = Color::new(255, 127, 0);
+pub const DARKER_ORANGE: Color = Color::new(127, 63, 0);
+pub const YELLOW: Color = Color::new(255, 255, 0);
+pub const GOLD: Color = Color::new(255, 191, 0);
+pub const LIGHT_YELLOW: Color = Color::new(255, 255, 63);
+pub const GREEN: Color = Color::new(0, 255, 0);
+pub const LIGHT_GREEN: Color = Color::new(63, 255, 63);
+pub const DARKER_GREEN: Color = Color::new(0, 127, 0);
+pub const DESATURATED_GREEN: Color = Color::new(63, 127, 63);
+pub const SKY: Color = Color::new(0, 191, 255);
+pub const LIGHT_BLUE: Color = Color::new(63, 63, 255);
+pub const LIGHT_CYAN: Color = Color::new(63, 255, 255);
+pub const VIOLET: Color = Color::new(127, 0, 255);
+pub const LIGHT_VIOLET: Color = Color::new(159, 63, 255);
+pub const PINK: Color = Color::new(255, 0, 127);
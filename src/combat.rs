@@ -0,0 +1,232 @@
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::colors::Color;
+use crate::dice::Dice;
+use crate::object::Object;
+use crate::Game;
+
+// an attack hits when a d20 plus the attacker's accuracy comes to at least
+// this plus the target's evasion
+const HIT_TARGET: i32 = 8;
+// a natural 20 always hits, and rolls the damage dice twice; a natural 1
+// always misses
+const CRITICAL_ROLL: i32 = 20;
+const FUMBLE_ROLL: i32 = 1;
+
+// What a blow, a spell or a breath does its harm with.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DamageKind {
+    #[default]
+    Physical,
+    Fire,
+    Cold,
+    Lightning,
+    Poison,
+}
+
+impl DamageKind {
+    pub fn name(self) -> &'static str {
+        use DamageKind::*;
+        match self {
+            Physical => "physical",
+            Fire => "fire",
+            Cold => "cold",
+            Lightning => "lightning",
+            Poison => "poison",
+        }
+    }
+}
+
+// How much less of each kind of damage gets through, in percent: 100 is
+// immune, and below 0 is a weakness, so -50 takes half as much again.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Resistances {
+    pub physical: i32,
+    pub fire: i32,
+    pub cold: i32,
+    pub lightning: i32,
+    pub poison: i32,
+}
+
+impl Resistances {
+    pub fn get(&self, kind: DamageKind) -> i32 {
+        use DamageKind::*;
+        match kind {
+            Physical => self.physical,
+            Fire => self.fire,
+            Cold => self.cold,
+            Lightning => self.lightning,
+            Poison => self.poison,
+        }
+    }
+}
+
+// what's left of the damage after a resistance (or weakness), rounded to the
+// nearest hit point. nothing resists more than all of it
+pub fn resist(damage: i32, resistance: i32) -> i32 {
+    let resistance = resistance.min(100);
+    (damage.max(0) * (100 - resistance) + 50) / 100
+}
+
+// Everything that went into one attack, kept for the combat log.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AttackRoll {
+    // the d20, and what it came to with the attacker's accuracy
+    pub roll: i32,
+    pub to_hit: i32,
+    // what it had to come to
+    pub needed: i32,
+    pub hit: bool,
+    pub critical: bool,
+    pub dice: Dice,
+    pub kind: DamageKind,
+    // the damage dice and bonus, before the target's armour
+    pub rolled: i32,
+    // how much of it the armour took, and what got through; `damage` is what
+    // goes to `take_damage`, and `taken` what's left of it after resistances
+    pub absorbed: i32,
+    pub damage: i32,
+    pub taken: i32,
+}
+
+impl AttackRoll {
+    // all of the numbers, for anyone who wants to see them
+    pub fn breakdown(&self) -> String {
+        let to_hit = format!(
+            "d20 {} + {} = {} vs {}",
+            self.roll,
+            self.to_hit - self.roll,
+            self.to_hit,
+            self.needed
+        );
+        if !self.hit {
+            return format!("{}: miss", to_hit);
+        }
+        format!(
+            "{}: {}; {} damage {}{} = {}, armour -{}, resisted to {}",
+            to_hit,
+            if self.critical { "critical" } else { "hit" },
+            self.kind.name(),
+            self.dice,
+            if self.critical { " (dice twice)" } else { "" },
+            self.rolled,
+            self.absorbed,
+            self.taken
+        )
+    }
+}
+
+// the attacker tries to hurt the target with the given damage: first to
+// get past its guard, then past its armour, which stops anything from none
+// up to all of its defense
+pub fn roll_attack(
+    attacker: &Object,
+    target: &Object,
+    dice: Dice,
+    kind: DamageKind,
+    game: &mut Game,
+) -> AttackRoll {
+    let roll = game.rng.gen_range(1, 21);
+    let to_hit = roll + attacker.accuracy();
+    let needed = HIT_TARGET + target.evasion();
+    let critical = roll >= CRITICAL_ROLL;
+    let hit = critical || (roll > FUMBLE_ROLL && to_hit >= needed);
+    let mut attack = AttackRoll {
+        roll,
+        to_hit,
+        needed,
+        hit,
+        critical,
+        dice,
+        kind,
+        rolled: 0,
+        absorbed: 0,
+        damage: 0,
+        taken: 0,
+    };
+    if !hit {
+        return attack;
+    }
+    attack.rolled = dice.roll(&mut game.rng).max(0);
+    if critical {
+        attack.rolled += dice.dice_only().roll(&mut game.rng);
+    }
+    let armour = target.defense(game).max(0);
+    attack.absorbed = game.rng.gen_range(0, armour + 1).min(attack.rolled);
+    attack.damage = attack.rolled - attack.absorbed;
+    attack.taken = target.resisted(attack.damage, kind, game);
+    attack
+}
+
+// tell the player how it went, and put the numbers in the combat log
+pub fn log_attack(
+    attacker: &str,
+    verb: &str,
+    target: &str,
+    attack: &AttackRoll,
+    color: Color,
+    game: &mut Game,
+) {
+    let message = if !attack.hit {
+        format!("{} {} {} but misses.", attacker, verb, target)
+    } else if attack.taken == 0 {
+        format!("{} {} {} but it has no effect!", attacker, verb, target)
+    } else if attack.critical {
+        format!(
+            "{} {} {} for {} hit points. A critical hit!",
+            attacker, verb, target, attack.taken
+        )
+    } else {
+        format!(
+            "{} {} {} for {} hit points.",
+            attacker, verb, target, attack.taken
+        )
+    };
+    game.messages.add(message, color);
+    game.combat_log.add(
+        format!("{} -> {}: {}", attacker, target, attack.breakdown()),
+        color,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resistances_take_their_share() {
+        assert_eq!(resist(10, 0), 10);
+        assert_eq!(resist(10, 50), 5);
+        assert_eq!(resist(10, 100), 0);
+        // rounded to the nearest hit point
+        assert_eq!(resist(5, 50), 3);
+        assert_eq!(resist(3, 90), 0);
+    }
+
+    #[test]
+    fn weaknesses_add_to_the_damage() {
+        assert_eq!(resist(10, -50), 15);
+        assert_eq!(resist(10, -100), 20);
+    }
+
+    #[test]
+    fn nothing_resists_more_than_everything() {
+        assert_eq!(resist(10, 150), 0);
+        assert_eq!(resist(-5, 0), 0);
+    }
+
+    #[test]
+    fn resistances_by_kind() {
+        let resistances = Resistances {
+            fire: 50,
+            poison: 100,
+            ..Default::default()
+        };
+        assert_eq!(resistances.get(DamageKind::Fire), 50);
+        assert_eq!(resistances.get(DamageKind::Poison), 100);
+        assert_eq!(resistances.get(DamageKind::Physical), 0);
+    }
+}
@@ -0,0 +1,155 @@
+use std::fmt;
+use std::str::FromStr;
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+// A handful of dice and something added to what they show, written the usual
+// way: "2d6+1", "d8", "1d4-1", or just a number for something that always
+// comes out the same.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Dice {
+    pub count: i32,
+    pub sides: i32,
+    pub bonus: i32,
+}
+
+impl Dice {
+    pub const fn new(count: i32, sides: i32, bonus: i32) -> Self {
+        Dice {
+            count,
+            sides,
+            bonus,
+        }
+    }
+
+    pub fn roll<R: Rng>(&self, rng: &mut R) -> i32 {
+        let rolled: i32 = (0..self.count)
+            .map(|_| rng.gen_range(1, self.sides + 1))
+            .sum();
+        rolled + self.bonus
+    }
+
+    // the dice on their own, without the bonus; rolled again on a critical
+    pub fn dice_only(&self) -> Dice {
+        Dice::new(self.count, self.sides, 0)
+    }
+}
+
+impl fmt::Display for Dice {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.count == 0 {
+            return write!(f, "{}", self.bonus);
+        }
+        write!(f, "{}d{}", self.count, self.sides)?;
+        match self.bonus {
+            0 => Ok(()),
+            bonus if bonus > 0 => write!(f, "+{}", bonus),
+            bonus => write!(f, "{}", bonus),
+        }
+    }
+}
+
+impl FromStr for Dice {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let error = || format!("`{}` isn't dice, like `2d6+1`", text);
+        let text = text.trim();
+        let (dice, bonus) = match text.find(['+', '-']) {
+            Some(sign) if text.contains('d') => (&text[..sign], &text[sign..]),
+            _ => (text, ""),
+        };
+        let bonus = match bonus {
+            "" => 0,
+            bonus => bonus.parse().map_err(|_| error())?,
+        };
+        match dice.split_once('d') {
+            Some((count, sides)) => {
+                let count = match count {
+                    "" => 1,
+                    count => count.parse().map_err(|_| error())?,
+                };
+                let sides = sides.parse().map_err(|_| error())?;
+                if count < 0 || sides < 1 {
+                    return Err(error());
+                }
+                Ok(Dice::new(count, sides, bonus))
+            }
+            // no dice at all: always the same
+            None => Ok(Dice::new(0, 1, dice.parse().map_err(|_| error())?)),
+        }
+    }
+}
+
+impl TryFrom<String> for Dice {
+    type Error = String;
+
+    fn try_from(text: String) -> Result<Self, Self::Error> {
+        text.parse()
+    }
+}
+
+impl From<Dice> for String {
+    fn from(dice: Dice) -> Self {
+        dice.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::GameRng;
+
+    #[test]
+    fn parses_dice() {
+        assert_eq!("2d6+1".parse(), Ok(Dice::new(2, 6, 1)));
+        assert_eq!("d8".parse(), Ok(Dice::new(1, 8, 0)));
+        assert_eq!("1d4-1".parse(), Ok(Dice::new(1, 4, -1)));
+        assert_eq!(" 3d6 ".parse(), Ok(Dice::new(3, 6, 0)));
+        assert_eq!("4".parse(), Ok(Dice::new(0, 1, 4)));
+        assert_eq!("-2".parse(), Ok(Dice::new(0, 1, -2)));
+    }
+
+    #[test]
+    fn rejects_anything_else() {
+        for text in ["", "d", "2d", "2d0", "-1d6", "2d6+", "2d6+x", "2x6", "lots"] {
+            assert_eq!(
+                text.parse::<Dice>(),
+                Err(format!("`{}` isn't dice, like `2d6+1`", text))
+            );
+        }
+    }
+
+    #[test]
+    fn writes_dice_the_way_they_are_read() {
+        for text in ["2d6+1", "1d8", "1d4-1", "4"] {
+            assert_eq!(text.parse::<Dice>().unwrap().to_string(), text);
+        }
+    }
+
+    #[test]
+    fn reads_dice_written_out() {
+        assert_eq!(
+            serde_json::from_str::<Dice>("\"1d6+2\"").unwrap(),
+            Dice::new(1, 6, 2)
+        );
+        assert_eq!(
+            serde_json::from_str::<Dice>("\"3\"").unwrap(),
+            Dice::new(0, 1, 3)
+        );
+        assert!(serde_json::from_str::<Dice>("3").is_err());
+        assert!(serde_json::from_str::<Dice>("\"3 dice\"").is_err());
+    }
+
+    #[test]
+    fn rolls_within_the_dice() {
+        let mut rng = GameRng::new(1);
+        let dice = Dice::new(2, 6, 1);
+        for _ in 0..1000 {
+            assert!((3..=13).contains(&dice.roll(&mut rng)));
+        }
+        assert_eq!(Dice::new(0, 1, 4).roll(&mut rng), 4);
+    }
+}
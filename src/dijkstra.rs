@@ -0,0 +1,235 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+use crate::map::{Map, MAP_HEIGHT, MAP_WIDTH};
+use crate::object::Object;
+use crate::path::step_cost;
+
+// running away is worth a little more than the distance it gains, so fleeing
+// monsters prefer ways out to corners (the flee map is the chase map times
+// -FLEE_NUMERATOR / FLEE_DENOMINATOR, smoothed out again)
+const FLEE_NUMERATOR: i32 = 6;
+const FLEE_DENOMINATOR: i32 = 5;
+
+// A value for every tile of the map: how far it is from the nearest goal, in
+// turns. Anything standing on it can get closer to the goals by stepping to
+// the lowest neighbour, so one map serves every monster chasing the same
+// thing. Objects aren't part of it, so it stays valid while they move about.
+#[derive(Clone, Debug)]
+pub struct DijkstraMap {
+    // None for the tiles that can't reach any goal
+    values: Vec<Option<i32>>,
+}
+
+impl DijkstraMap {
+    pub fn new(map: &Map, goals: &[(i32, i32)]) -> Self {
+        let mut values = vec![None; (MAP_WIDTH * MAP_HEIGHT) as usize];
+        for &goal in goals {
+            values[index(goal)] = Some(0);
+        }
+        let mut dijkstra = DijkstraMap { values };
+        dijkstra.spread(map);
+        dijkstra
+    }
+
+    // a map that leads away from this one's goals, to wherever is furthest
+    // from them and not into dead ends when there's another way
+    pub fn flee(&self, map: &Map) -> Self {
+        let values = self
+            .values
+            .iter()
+            .map(|value| value.map(|value| -value * FLEE_NUMERATOR / FLEE_DENOMINATOR))
+            .collect();
+        let mut dijkstra = DijkstraMap { values };
+        dijkstra.spread(map);
+        dijkstra
+    }
+
+    pub fn value(&self, x: i32, y: i32) -> Option<i32> {
+        if x < 0 || y < 0 || x >= MAP_WIDTH || y >= MAP_HEIGHT {
+            return None;
+        }
+        self.values[index((x, y))]
+    }
+
+    // the step (dx, dy) from `from` to its lowest neighbour that nothing is
+    // standing on, or None if there's nowhere lower to go
+    pub fn step(&self, from: (i32, i32), objects: &[Object]) -> Option<(i32, i32)> {
+        let mut best = (self.value(from.0, from.1)?, None);
+        for dx in -1..=1 {
+            for dy in -1..=1 {
+                let (x, y) = (from.0 + dx, from.1 + dy);
+                let value = match self.value(x, y) {
+                    Some(value) => value,
+                    None => continue,
+                };
+                let occupied = objects
+                    .iter()
+                    .any(|object| object.blocks && object.pos() == (x, y));
+                if value < best.0 && !occupied {
+                    best = (value, Some((dx, dy)));
+                }
+            }
+        }
+        best.1
+    }
+
+    // Dijkstra's algorithm, started from every tile that already has a
+    // value: lower each tile to its cheapest neighbour plus the cost of
+    // stepping onto it
+    fn spread(&mut self, map: &Map) {
+        let mut open: BinaryHeap<_> = (0..MAP_WIDTH)
+            .flat_map(|x| (0..MAP_HEIGHT).map(move |y| (x, y)))
+            .filter_map(|tile| self.values[index(tile)].map(|value| Reverse((value, tile))))
+            .collect();
+        while let Some(Reverse((value, (x, y)))) = open.pop() {
+            if self.values[index((x, y))] != Some(value) {
+                // lowered since this was queued
+                continue;
+            }
+            for dx in -1..=1 {
+                for dy in -1..=1 {
+                    let next = (x + dx, y + dy);
+                    let cost = match step_cost(map, next.0, next.1) {
+                        Some(cost) if next != (x, y) => cost as i32,
+                        _ => continue,
+                    };
+                    let next_value = value + cost;
+                    if self.values[index(next)].is_none_or(|old| next_value < old) {
+                        self.values[index(next)] = Some(next_value);
+                        open.push(Reverse((next_value, next)));
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn index((x, y): (i32, i32)) -> usize {
+    (x + y * MAP_WIDTH) as usize
+}
+
+// The maps leading to and away from the player, kept between turns and only
+// worked out again once the player has moved or the terrain has changed (a
+// door opened, or a new level).
+#[derive(Default)]
+pub struct PlayerMaps {
+    player: (i32, i32),
+    // which tiles could be walked into when the maps were made
+    terrain: Vec<Option<u32>>,
+    chase: Option<DijkstraMap>,
+    flee: Option<DijkstraMap>,
+}
+
+impl PlayerMaps {
+    // leads to the player
+    pub fn chase(&mut self, map: &Map, player: (i32, i32)) -> &DijkstraMap {
+        self.refresh(map, player);
+        self.chase
+            .get_or_insert_with(|| DijkstraMap::new(map, &[player]))
+    }
+
+    // leads away from the player
+    pub fn flee(&mut self, map: &Map, player: (i32, i32)) -> &DijkstraMap {
+        self.refresh(map, player);
+        if self.flee.is_none() {
+            let chase = self
+                .chase
+                .get_or_insert_with(|| DijkstraMap::new(map, &[player]));
+            self.flee = Some(chase.flee(map));
+        }
+        self.flee.as_ref().unwrap()
+    }
+
+    // forget the maps if they're out of date
+    fn refresh(&mut self, map: &Map, player: (i32, i32)) {
+        let terrain: Vec<_> = (0..MAP_WIDTH)
+            .flat_map(|x| (0..MAP_HEIGHT).map(move |y| step_cost(map, x, y)))
+            .collect();
+        if player != self.player || terrain != self.terrain {
+            self.player = player;
+            self.terrain = terrain;
+            self.chase = None;
+            self.flee = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::colors::WHITE;
+    use crate::map::{Door, Tile};
+
+    // solid rock, with a room dug out from (1, 1) to (10, 10)
+    fn room() -> Map {
+        let mut map = vec![vec![Tile::wall(); MAP_HEIGHT as usize]; MAP_WIDTH as usize];
+        for column in &mut map[1..=10] {
+            for tile in &mut column[1..=10] {
+                *tile = Tile::empty();
+            }
+        }
+        map
+    }
+
+    #[test]
+    fn counts_the_steps_to_the_nearest_goal() {
+        let dijkstra = DijkstraMap::new(&room(), &[(2, 2), (9, 9)]);
+        assert_eq!(dijkstra.value(2, 2), Some(0));
+        assert_eq!(dijkstra.value(4, 3), Some(2));
+        assert_eq!(dijkstra.value(9, 6), Some(3));
+        assert_eq!(dijkstra.value(0, 0), None);
+        assert_eq!(dijkstra.value(-1, 5), None);
+    }
+
+    #[test]
+    fn goes_the_long_way_around_walls_and_doors() {
+        let mut map = room();
+        // a wall across the room at x = 5, with a door at the bottom
+        map[5][1..=9].fill(Tile::wall());
+        map[5][10] = Tile::door(Door::Closed);
+        let dijkstra = DijkstraMap::new(&map, &[(3, 2)]);
+        // down to the door, which takes a turn to open, and back up
+        assert_eq!(dijkstra.value(7, 2), Some(17));
+        map[5][10] = Tile::door(Door::Locked);
+        let dijkstra = DijkstraMap::new(&map, &[(3, 2)]);
+        assert_eq!(dijkstra.value(7, 2), None);
+    }
+
+    #[test]
+    fn steps_downhill_past_no_one() {
+        let dijkstra = DijkstraMap::new(&room(), &[(2, 2)]);
+        assert_eq!(dijkstra.step((6, 6), &[]), Some((-1, -1)));
+        assert_eq!(dijkstra.step((2, 2), &[]), None);
+        // the only closer tile is taken
+        let orc = Object::new(5, 5, 'o', "orc", WHITE, true);
+        assert_eq!(dijkstra.step((6, 6), &[orc]), None);
+    }
+
+    #[test]
+    fn fleeing_gets_further_away() {
+        let map = room();
+        let chase = DijkstraMap::new(&map, &[(2, 2)]);
+        let flee = chase.flee(&map);
+        let mut position = (4, 4);
+        for _ in 0..4 {
+            let (dx, dy) = flee.step(position, &[]).unwrap();
+            let next = (position.0 + dx, position.1 + dy);
+            assert!(chase.value(next.0, next.1) > chase.value(position.0, position.1));
+            position = next;
+        }
+    }
+
+    #[test]
+    fn player_maps_follow_the_player_and_the_doors() {
+        let mut map = room();
+        let mut maps = PlayerMaps::default();
+        assert_eq!(maps.chase(&map, (2, 2)).value(6, 2), Some(4));
+        assert_eq!(maps.chase(&map, (3, 2)).value(6, 2), Some(3));
+        // a door shut and locked between them
+        map[5].fill(Tile::wall());
+        map[5][2] = Tile::door(Door::Locked);
+        assert_eq!(maps.chase(&map, (3, 2)).value(6, 2), None);
+        assert_eq!(maps.flee(&map, (3, 2)).value(6, 2), None);
+    }
+}
@@ -0,0 +1,104 @@
+use crate::map::{Map, MAP_HEIGHT, MAP_WIDTH};
+
+// The set of tiles visible from a point, computed straight from the map's
+// `block_sight` flags so it needs no console or libtcod.
+#[derive(Clone, Debug)]
+pub struct FovMap {
+    width: i32,
+    height: i32,
+    visible: Vec<bool>,
+}
+
+impl FovMap {
+    pub fn new(width: i32, height: i32) -> Self {
+        FovMap {
+            width,
+            height,
+            visible: vec![false; (width * height) as usize],
+        }
+    }
+
+    pub fn is_in_fov(&self, x: i32, y: i32) -> bool {
+        self.in_bounds(x, y) && self.visible[(x + y * self.width) as usize]
+    }
+
+    // cast a ray from the origin to every tile on the edge of the square
+    // around it, stopping each ray at the first tile that blocks sight.
+    // walls hit by a ray are lit, like libtcod's `light_walls`
+    pub fn compute_fov(&mut self, map: &Map, x: i32, y: i32, radius: i32) {
+        self.visible.iter_mut().for_each(|v| *v = false);
+        if !self.in_bounds(x, y) {
+            return;
+        }
+        self.set_visible(x, y);
+
+        let mut edge = vec![];
+        for i in -radius..=radius {
+            edge.push((x + i, y - radius));
+            edge.push((x + i, y + radius));
+            edge.push((x - radius, y + i));
+            edge.push((x + radius, y + i));
+        }
+        for (edge_x, edge_y) in edge {
+            for (ray_x, ray_y) in line((x, y), (edge_x, edge_y)) {
+                if !self.in_bounds(ray_x, ray_y)
+                    || (ray_x - x).pow(2) + (ray_y - y).pow(2) > radius.pow(2)
+                {
+                    break;
+                }
+                self.set_visible(ray_x, ray_y);
+                if map[ray_x as usize][ray_y as usize].block_sight {
+                    break;
+                }
+            }
+        }
+    }
+
+    fn in_bounds(&self, x: i32, y: i32) -> bool {
+        x >= 0 && y >= 0 && x < self.width && y < self.height
+    }
+
+    fn set_visible(&mut self, x: i32, y: i32) {
+        self.visible[(x + y * self.width) as usize] = true;
+    }
+}
+
+impl Default for FovMap {
+    fn default() -> Self {
+        FovMap::new(MAP_WIDTH, MAP_HEIGHT)
+    }
+}
+
+// whether `to` can be seen from `from`: nothing that blocks sight on the
+// straight line between them. one ray, for when a whole field of view would
+// be wasted on a single tile
+pub fn in_sight(map: &Map, from: (i32, i32), to: (i32, i32)) -> bool {
+    line(from, to)
+        .into_iter()
+        .take_while(|&tile| tile != to)
+        .all(|(x, y)| !map[x as usize][y as usize].block_sight)
+}
+
+// the tiles on a Bresenham line between two points, excluding the start
+pub fn line(from: (i32, i32), to: (i32, i32)) -> Vec<(i32, i32)> {
+    let (mut x, mut y) = from;
+    let dx = (to.0 - x).abs();
+    let dy = -(to.1 - y).abs();
+    let step_x = if x < to.0 { 1 } else { -1 };
+    let step_y = if y < to.1 { 1 } else { -1 };
+    let mut error = dx + dy;
+    let mut points = vec![];
+    while (x, y) != to {
+        let doubled = 2 * error;
+        if doubled >= dy {
+            error += dy;
+            x += step_x;
+        }
+        if doubled <= dx {
+            error += dx;
+            y += step_y;
+        }
+        points.push((x, y));
+    }
+    points
+}
@@ -0,0 +1,565 @@
+use serde::{Deserialize, Serialize};
+
+use crate::colors::*;
+use crate::combat::{log_attack, roll_attack, DamageKind};
+use crate::fov::line;
+use crate::map::make_item;
+use crate::object::{mut_two, Object, Slot};
+use crate::scheduler::{ATTACK_COST, DRINK_COST, EQUIP_COST, READ_COST};
+use crate::status::StatusKind;
+use crate::{Game, Ui, PLAYER};
+
+// What an item does when used, and how much. Every kind of item is made up
+// in ITEMS_FILE, from one of these.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub enum Item {
+    // heals the player
+    Heal {
+        amount: i32,
+    },
+    // strikes the closest monster in sight
+    DamageNearest {
+        damage: i32,
+        range: i32,
+        #[serde(default)]
+        kind: DamageKind,
+    },
+    // hurts everything around a tile the player picks, the player too
+    AreaDamage {
+        damage: i32,
+        radius: i32,
+        #[serde(default)]
+        kind: DamageKind,
+    },
+    // puts a monster the player picks in a state, for a while
+    Status {
+        status: StatusKind,
+        num_turns: i32,
+        range: i32,
+    },
+    // puts the player in a state, for a while
+    SelfStatus {
+        status: StatusKind,
+        num_turns: i32,
+    },
+    // worn or wielded, for its equipment bonuses
+    Equip,
+    // opens locked doors
+    Key,
+    // shot from a bow or a crossbow, one at a time from the stack
+    Ammo {
+        ammo: AmmoKind,
+    },
+}
+
+// What a launcher shoots; only the right kind will do.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AmmoKind {
+    Arrow,
+    Bolt,
+}
+
+impl Item {
+    // the energy it takes to use: potions are quicker to drink than scrolls
+    // are to read
+    pub fn use_cost(self) -> i32 {
+        use Item::*;
+        match self {
+            Heal { .. } | SelfStatus { .. } => DRINK_COST,
+            DamageNearest { .. } | AreaDamage { .. } | Status { .. } => READ_COST,
+            Equip => EQUIP_COST,
+            // walking into the door, or firing, does the work
+            Key | Ammo { .. } => 0,
+        }
+    }
+}
+
+enum UseResult {
+    UsedUp,
+    Cancelled,
+    UsedAndKept,
+}
+
+// how far something can be thrown, and how far a potion splashes when it
+// shatters: everything right next to where it breaks
+const THROW_RANGE: f32 = 8.0;
+const SPLASH_RADIUS: f32 = 1.5;
+
+pub fn use_item(inventory_id: usize, ui: &mut dyn Ui, game: &mut Game, objects: &mut [Object]) {
+    use Item::*;
+    // do whatever the item is made to do, as strongly as it is made to
+    if let Some(item) = game.inventory[inventory_id].item {
+        let name = game.inventory[inventory_id].name.clone();
+        let result = match item {
+            Heal { amount } => cast_heal(amount, game, objects),
+            DamageNearest {
+                damage,
+                range,
+                kind,
+            } => cast_damage_nearest(&name, damage, range, kind, game, objects),
+            AreaDamage {
+                damage,
+                radius,
+                kind,
+            } => cast_area_damage(&name, damage, radius, kind, ui, game, objects),
+            Status {
+                status,
+                num_turns,
+                range,
+            } => cast_status(status, num_turns, range, ui, game, objects),
+            SelfStatus { status, num_turns } => cast_self_status(status, num_turns, game, objects),
+            Equip => toggle_equipment(inventory_id, game),
+            Key => use_key(game),
+            Ammo { .. } => use_ammo(game),
+        };
+        if !matches!(result, UseResult::Cancelled) {
+            objects[PLAYER].spend_energy(item.use_cost());
+        }
+        match result {
+            UseResult::UsedUp => {
+                // destroy after use
+                game.inventory.remove(inventory_id);
+            }
+            UseResult::Cancelled => {
+                // do not use
+                game.messages.add("Cancelled", WHITE);
+            }
+            UseResult::UsedAndKept => {
+                // use and dont destroy
+            }
+        }
+    } else {
+        game.messages.add(
+            format!("The {} cannot be used.", game.inventory[inventory_id].name),
+            WHITE,
+        );
+    }
+}
+
+fn toggle_equipment(inventory_id: usize, game: &mut Game) -> UseResult {
+    let equipment = match game.inventory[inventory_id].equipment {
+        Some(equipment) => equipment,
+        None => return UseResult::Cancelled,
+    };
+    if equipment.equipped {
+        game.inventory[inventory_id].dequip(&mut game.messages);
+    } else {
+        if let Some(current) = get_equipped_in_slot(equipment.slot, &game.inventory) {
+            game.inventory[current].dequip(&mut game.messages);
+        }
+        game.inventory[inventory_id].equip(&mut game.messages);
+    }
+    UseResult::UsedAndKept
+}
+
+fn use_key(game: &mut Game) -> UseResult {
+    // keys are used by walking into a locked door
+    game.messages
+        .add("Walk into a locked door to open it with the key.", WHITE);
+    UseResult::UsedAndKept
+}
+
+fn use_ammo(game: &mut Game) -> UseResult {
+    // ammunition is used by firing it
+    game.messages.add(
+        "Equip a bow or a crossbow and press f to fire these.",
+        WHITE,
+    );
+    UseResult::UsedAndKept
+}
+
+pub fn get_equipped_in_slot(slot: Slot, inventory: &[Object]) -> Option<usize> {
+    for (inventory_id, item) in inventory.iter().enumerate() {
+        if item
+            .equipment
+            .as_ref()
+            .is_some_and(|e| e.equipped && e.slot == slot)
+        {
+            return Some(inventory_id);
+        }
+    }
+    None
+}
+
+fn cast_heal(amount: i32, game: &mut Game, objects: &mut [Object]) -> UseResult {
+    // heal the player
+    let player = &mut objects[PLAYER];
+    if let Some(fighter) = player.fighter {
+        if fighter.hp == player.max_hp(game) {
+            game.messages.add("You are already at full health", YELLOW);
+            return UseResult::Cancelled;
+        }
+        game.messages
+            .add("Your wounds start to feel better!", LIGHT_VIOLET);
+        player.heal(amount, game);
+        return UseResult::UsedUp;
+    }
+    UseResult::Cancelled
+}
+
+fn cast_self_status(
+    status: StatusKind,
+    num_turns: i32,
+    game: &mut Game,
+    objects: &mut [Object],
+) -> UseResult {
+    objects[PLAYER].add_status(status, num_turns);
+    game.messages
+        .add(format!("You are {}!", status.name()), status.color());
+    UseResult::UsedUp
+}
+
+fn cast_damage_nearest(
+    name: &str,
+    damage: i32,
+    range: i32,
+    kind: DamageKind,
+    game: &mut Game,
+    objects: &mut [Object],
+) -> UseResult {
+    // find closest enemy in range and FOV and hit it
+    let monster_id = closest_monster(game, objects, range);
+    if let Some(monster_id) = monster_id {
+        game.messages.add(
+            format!(
+                "The {} strikes the {} with {} power! \
+                It damages for {} HP.",
+                name,
+                objects[monster_id].name,
+                kind.name(),
+                objects[monster_id].resisted(damage, kind, game)
+            ),
+            LIGHT_BLUE,
+        );
+        let (player, monster) = mut_two(PLAYER, monster_id, objects);
+        player.damage(monster, damage, kind, game);
+        UseResult::UsedUp
+    } else {
+        // no enemies within range
+        game.messages.add("No enemy found close enough to strike.", RED);
+        UseResult::Cancelled
+    }
+}
+
+fn cast_status(
+    status: StatusKind,
+    num_turns: i32,
+    range: i32,
+    ui: &mut dyn Ui,
+    game: &mut Game,
+    objects: &mut [Object],
+) -> UseResult {
+    // ask the player for a target
+    let verb = match status {
+        StatusKind::Confused => "confuse".to_string(),
+        _ => format!("make {}", status.name()),
+    };
+    game.messages.add(
+        format!(
+            "Left-click an enemy to {} it, or right-click to cancel.",
+            verb
+        ),
+        LIGHT_CYAN,
+    );
+
+    let monster_id = target_monster(ui, game, objects, Some(range as f32));
+    if let Some(monster_id) = monster_id {
+        // it wears off by itself after a while
+        objects[monster_id].add_status(status, num_turns);
+        let name = &objects[monster_id].name;
+        let message = match status {
+            StatusKind::Confused => format!(
+                "The eyes of the {} look vacant, as it stumbles around!",
+                name
+            ),
+            _ => format!("The {} is {}!", name, status.name()),
+        };
+        game.messages.add(message, status.color());
+        UseResult::UsedUp
+    } else {
+        // no enemy found within range
+        game.messages.add("No enemy found close enough to strike.", RED);
+        UseResult::Cancelled
+    }
+}
+
+fn cast_area_damage(
+    name: &str,
+    damage: i32,
+    radius: i32,
+    kind: DamageKind,
+    ui: &mut dyn Ui,
+    game: &mut Game,
+    objects: &mut [Object],
+) -> UseResult {
+    // ask the player for a target tile to set it off at
+    game.messages.add(
+        format!(
+            "Left-click a tile for the {} to be cast at, or Right-click to cancel",
+            name
+        ),
+        LIGHT_RED,
+    );
+
+    let (x, y) = match ui.target_tile(game, objects, None) {
+        Some(tile_pos) => tile_pos,
+        None => return UseResult::Cancelled,
+    };
+    game.messages.add(
+        format!(
+            "The {} explodes, burning everything within {} tiles!",
+            name, radius,
+        ),
+        ORANGE,
+    );
+
+    let mut xp_to_gain = 0;
+    for (id, obj) in objects.iter_mut().enumerate() {
+        if obj.distance(x, y) <= radius as f32 && obj.fighter.is_some() {
+            game.messages.add(
+                format!(
+                    "The {} gets hit by the {} for {} HP.",
+                    obj.name,
+                    kind.name(),
+                    obj.resisted(damage, kind, game)
+                ),
+                ORANGE,
+            );
+            if let Some(xp) = obj.take_damage(damage, kind, game) {
+                if id != PLAYER {
+                    // no reward for self immolation
+                    xp_to_gain += xp;
+                } else {
+                    game.cause_of_death = Some(format!("their own {}", name));
+                }
+            }
+        }
+    }
+    objects[PLAYER].fighter.as_mut().unwrap().xp += xp_to_gain;
+    UseResult::UsedUp
+}
+
+// find closest enemy, upto a max range and in the player FOV
+pub fn closest_monster(game: &Game, objects: &[Object], max_range: i32) -> Option<usize> {
+    let mut closest_enemy = None;
+    let mut closest_dist = (max_range + 1) as f32;
+
+    for (id, object) in objects.iter().enumerate() {
+        if (id != PLAYER)
+            && object.fighter.is_some()
+            && object.ai.is_some()
+            && game.fov.is_in_fov(object.x, object.y)
+        {
+            // calc the dist bw obj and player
+            let dist = objects[PLAYER].distance_to(object);
+            if dist < closest_dist {
+                // close, remember it
+                closest_enemy = Some(id);
+                closest_dist = dist;
+            }
+        }
+    }
+    closest_enemy
+}
+
+// returns a clicked monster inside FOV up to a range, or None if
+// right-clicked
+pub fn target_monster(
+    ui: &mut dyn Ui,
+    game: &Game,
+    objects: &[Object],
+    max_range: Option<f32>,
+) -> Option<usize> {
+    loop {
+        match ui.target_tile(game, objects, max_range) {
+            Some((x, y)) => {
+                // return the first clicked monster, otherwise keep looping
+                for (id, obj) in objects.iter().enumerate() {
+                    if obj.pos() == (x, y) && obj.fighter.is_some() && id != PLAYER {
+                        return Some(id);
+                    }
+                }
+            }
+            None => return None,
+        }
+    }
+}
+
+pub fn pick_item_up(object_id: usize, game: &mut Game, objects: &mut Vec<Object>) {
+    // arrows and the like go on the stack already carried, even when
+    // there's no room for anything else
+    let stack = game
+        .inventory
+        .iter()
+        .position(|item| item.stacks_with(&objects[object_id]));
+    if let Some(stack) = stack {
+        let item = objects.swap_remove(object_id);
+        game.messages.add(
+            format!("You picked up {} {}!", item.count, item.name),
+            GREEN,
+        );
+        game.inventory[stack].count += item.count;
+        return;
+    }
+    if game.inventory.len() >= 26 {
+        game.messages.add(
+            format!(
+                "Your invectory is full, cannot pick up {}.",
+                objects[object_id].name
+            ),
+            RED,
+        );
+    } else {
+        let item = objects.swap_remove(object_id);
+        game.messages
+            .add(format!("You picked up a {}!", item.name), GREEN);
+        let index = game.inventory.len();
+        let slot = item.equipment.map(|e| e.slot);
+        game.inventory.push(item);
+
+        // automatically equip, if the corresponding slot is empty
+        if let Some(slot) = slot {
+            if get_equipped_in_slot(slot, &game.inventory).is_none() {
+                game.inventory[index].equip(&mut game.messages);
+            }
+        }
+    }
+}
+
+pub fn drop_item(inventory_id: usize, game: &mut Game, objects: &mut Vec<Object>) {
+    let mut item = game.inventory.remove(inventory_id);
+    if item.equipment.is_some() {
+        item.dequip(&mut game.messages);
+    }
+    item.set_pos(objects[PLAYER].x, objects[PLAYER].y);
+    game.messages
+        .add(format!("You dropped a {}.", item.name), YELLOW);
+    objects.push(item);
+}
+
+// the player throws something at a tile they pick. it flies along the line
+// until it hits a wall or someone: potions shatter and splash whoever's
+// around, weapons made for throwing hurt whoever they hit, and anything else
+// just lands there. returns whether anything was thrown
+pub fn throw_item(
+    inventory_id: usize,
+    ui: &mut dyn Ui,
+    game: &mut Game,
+    objects: &mut [Object],
+) -> bool {
+    game.messages.add(
+        format!(
+            "Left-click a tile to throw the {} at, or right-click to cancel.",
+            game.inventory[inventory_id].name
+        ),
+        LIGHT_CYAN,
+    );
+    let target = match ui.target_tile(game, objects, Some(THROW_RANGE)) {
+        Some(target) => target,
+        None => {
+            game.messages.add("Cancelled", WHITE);
+            return false;
+        }
+    };
+    let mut item = take_one(inventory_id, game);
+    objects[PLAYER].spend_energy(ATTACK_COST);
+
+    let from = objects[PLAYER].pos();
+    let mut landing = from;
+    let mut hit_id = None;
+    for (x, y) in line(from, target) {
+        if game.map[x as usize][y as usize].blocked {
+            break;
+        }
+        landing = (x, y);
+        hit_id = objects
+            .iter()
+            .position(|object| object.blocks && object.fighter.is_some() && object.pos() == (x, y));
+        if hit_id.is_some() {
+            break;
+        }
+    }
+
+    if matches!(item.item, Some(Item::Heal { .. } | Item::SelfStatus { .. })) {
+        shatter(&item, landing, game, objects);
+        return true;
+    }
+    let thrown_damage = item.equipment.filter(|e| e.throwing).and_then(|e| e.damage);
+    match (hit_id, thrown_damage) {
+        (Some(hit_id), Some(dice)) => {
+            let verb = format!("throws the {} at", item.name);
+            let (player, target) = mut_two(PLAYER, hit_id, objects);
+            let attack = roll_attack(player, target, dice, DamageKind::Physical, game);
+            log_attack(&player.name, &verb, &target.name, &attack, WHITE, game);
+            if attack.damage > 0 {
+                player.damage(target, attack.damage, DamageKind::Physical, game);
+            }
+        }
+        (Some(hit_id), None) => {
+            game.messages.add(
+                format!(
+                    "The {} bounces off the {}.",
+                    item.name, objects[hit_id].name
+                ),
+                WHITE,
+            );
+        }
+        (None, _) => {
+            game.messages
+                .add(format!("The {} lands on the floor.", item.name), WHITE);
+        }
+    }
+    // it can be picked up again from where it fell
+    item.set_pos(landing.0, landing.1);
+    game.dropped.push(item);
+    true
+}
+
+// one of the item at that place in the inventory, taken out of it
+fn take_one(inventory_id: usize, game: &mut Game) -> Object {
+    if game.inventory[inventory_id].count > 1 {
+        game.inventory[inventory_id].count -= 1;
+        let mut item = make_item(&game.inventory[inventory_id].name, 0, 0);
+        item.count = 1;
+        return item;
+    }
+    let mut item = game.inventory.remove(inventory_id);
+    if item.equipment.is_some_and(|e| e.equipped) {
+        item.dequip(&mut game.messages);
+    }
+    item
+}
+
+// a thrown potion breaks, and everyone it splashes gets what they'd have got
+// from drinking it, friend or foe
+fn shatter(potion: &Object, (x, y): (i32, i32), game: &mut Game, objects: &mut [Object]) {
+    game.messages
+        .add(format!("The {} shatters!", potion.name), LIGHT_VIOLET);
+    for (id, object) in objects.iter_mut().enumerate() {
+        if !object.alive || object.fighter.is_none() || object.distance(x, y) > SPLASH_RADIUS {
+            continue;
+        }
+        let (name, looks, is) = if id == PLAYER {
+            ("You".to_string(), "look", "are")
+        } else {
+            (format!("The {}", object.name), "looks", "is")
+        };
+        match potion.item {
+            Some(Item::Heal { amount }) => {
+                object.heal(amount, game);
+                game.messages
+                    .add(format!("{} {} healthier.", name, looks), LIGHT_VIOLET);
+            }
+            Some(Item::SelfStatus { status, num_turns }) => {
+                object.add_status(status, num_turns);
+                game.messages.add(
+                    format!("{} {} {}!", name, is, status.name()),
+                    status.color(),
+                );
+            }
+            _ => {}
+        }
+    }
+}
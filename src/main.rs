@@ -1,39 +1,31 @@
 use std::env;
-use std::cmp;
-use std::error::Error;
-use std::fs::File;
-use std::io::{Read, Write};
+use std::process;
+use std::thread;
+use std::time::Duration;
 
-use rand::distributions::{IndependentSample, Weighted, WeightedChoice};
-use serde::{Deserialize, Serialize};
-
-use rand::Rng;
 use tcod::colors::*;
 use tcod::console::*;
-use tcod::map::{FovAlgorithm, Map as FovMap};
 use tcod::input::{self, Event, Key, Mouse};
 
+use roguelike::bot::{run_bot_games, BotConfig};
+use roguelike::fov::FovMap;
+use roguelike::map::{Door, MAP_HEIGHT, MAP_WIDTH};
+use roguelike::object::{Fighter, Object};
+use roguelike::raws::load_raws;
+use roguelike::replay::{load_replay, run_headless, summary, Replay, ReplayWriter, Replayer};
+use roguelike::rng::random_seed;
+use roguelike::travel::{Destination, Travel};
+use roguelike::vault::load_vaults;
+use roguelike::*;
+
 // size of the window
 const SCREEN_WIDTH: i32 = 80;
 const SCREEN_HEIGHT: i32 = 50;
 
-// size of the map
-const MAP_WIDTH: i32 = 80;
-const MAP_HEIGHT: i32 = 43;
-
-// room parameters
-const ROOM_MAX_SIZE: i32 = 10;
-const ROOM_MIN_SIZE: i32 = 6;
-const MAX_ROOMS: i32 = 30;
-// const MAX_ROOM_MONSTERS: i32 = 3;
-// const MAX_ROOM_ITEMS: i32 = 2;
-
-// FOV parameters
-const FOV_ALGO: FovAlgorithm = FovAlgorithm::Basic; 
-const FOV_LIGHT_WALLS: bool = true; 
-const TORCH_RADIUS: i32 = 10;
+const LIMIT_FPS: i32 = 20;
 
-const LIMIT_FPS: i32 = 20; 
+// every game is recorded here, to be played back with `--replay`
+const REPLAY_FILE: &str = "replay.jsonl";
 
 // environment
 const COLOR_DARK_WALL: Color = Color { r: 0, g: 0, b: 100 };
@@ -52,9 +44,7 @@ const COLOR_LIGHT_GROUND: Color = Color {
     g: 180,
     b: 50,
 };
-
-// player will always be the first object
-const PLAYER: usize = 0;
+const COLOR_DOOR: Color = Color { r: 191, g: 95, b: 0 };
 
 // sizes and coords for the GUI
 const BAR_WIDTH: i32 = 20;
@@ -64,1200 +54,98 @@ const MSG_X: i32 = BAR_WIDTH + 2;
 const MSG_WIDTH: i32 = SCREEN_WIDTH - BAR_WIDTH - 2;
 const MSG_HEIGHT: usize = PANEL_HEIGHT as usize - 1;
 const CHARACTER_SCREEN_WIDTH: i32 = 30;
+// the combat log shows this many of the latest attacks
+const COMBAT_LOG_WIDTH: i32 = 70;
+const COMBAT_LOG_LINES: usize = 20;
 
 // inventory
 const INVENTORY_WIDTH: i32 = 50;
 
-// item properties
-const HEAL_AMOUNT: i32 = 40;
-const LIGHTNING_RANGE: i32 = 5;
-const LIGHTNING_DAMAGE: i32 = 40;
-const CONFUSE_RANGE: i32 = 8;
-const CONFUSE_NUM_TURNS: i32 = 10;
-const FIREBALL_RADIUS: i32 = 3;
-const FIREBALL_DAMAGE: i32 = 25;
-
-// xp and levels
-const LEVEL_UP_BASE: i32 = 200;
-const LEVEL_UP_FACTOR: i32 = 150;
 const LEVEL_SCREEN_WIDTH: i32 = 40;
 
 struct Tcod {
     root: Root,
     con: Offscreen,
     panel: Offscreen,
-    fov: FovMap,
     key: Key,
     mouse: Mouse,
-}
-
-type Map = Vec<Vec<Tile>>;
-
-#[derive(Serialize, Deserialize)]
-struct Messages {
-    messages: Vec<(String, Color)>,
-}
-
-impl Messages {
-    pub fn new() -> Self {
-        Self { messages: vec![] }
-    }
-
-    // add the new msg as a tuple, with the text and color
-    pub fn add<T: Into<String>>(&mut self, message: T, color: Color) {
-        self.messages.push((message.into(), color));
-    }
-
-    // create a 'DoubleEndedIterator' over the msgs
-    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &(String, Color)> {
-        self.messages.iter()
-    }
-}
-
-#[derive(Serialize, Deserialize)]
-struct Game {
-    map: Map,
-    messages: Messages,
-    inventory: Vec<Object>,
-    dungeon_level : u32,
-}
-
-// A tile of the map and its properties
-#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
-struct Tile {
-    blocked: bool,
-    explored: bool,
-    block_sight: bool,
-}
-
-impl Tile {
-    pub fn empty() -> Self {
-        Tile {
-            blocked: false,
-            explored: false,
-            block_sight: false,
-        }
-    }
-
-    pub fn wall() -> Self {
-        Tile {
-            blocked: true,
-            explored: false,
-            block_sight: true,
-        }
-    }
-}
-
-// A rectangle on the map, used to characterise a room.
-#[derive(Clone, Copy, Debug)]
-struct Rect {
-    x1: i32,
-    y1: i32,
-    x2: i32,
-    y2: i32,
-}
-
-impl Rect {
-    pub fn new(x: i32, y: i32, w: i32, h: i32) -> Self {
-        Rect {
-            x1: x,
-            y1: y,
-            x2: x + w,
-            y2: y + h,
-        }
-    }
-
-    pub fn center(&self) -> (i32, i32) {
-        let center_x = (self.x1 + self.x2) / 2;
-        let center_y = (self.y1 + self.y2) / 2;
-        (center_x, center_y)
-    }
-
-    pub fn intersects_with(&self, other: &Rect) -> bool {
-        // returns true if this rectangle intersects with another one
-        (self.x1 <= other.x2)
-            && (self.x2 >= other.x1)
-            && (self.y1 <= other.y2)
-            && (self.y2 >= other.y1)
-    }
-}
-
-// This is a generic object: the player, a monster, an item, the stairs
-// It's always represented by a character on screen.
-#[derive(Debug, Serialize, Deserialize)]
-struct Object {
-    x: i32,
-    y: i32,
-    char: char,
-    color: Color,
-    name: String,
-    blocks: bool,
-    alive: bool,
-    fighter: Option<Fighter>,
-    ai: Option<Ai>,
-    item: Option<Item>,
-    always_visible: bool,
-    level: i32,
-    equipment: Option<Equipment>,
-}
-
-impl Object {
-    pub fn new(x: i32, y: i32, char: char, name: &str, color: Color, blocks: bool) -> Self {
-        Object {
-            x: x,
-            y: y,
-            char: char,
-            color: color,
-            name: name.into(),
-            blocks: blocks,
-            alive: false,
-            fighter: None,
-            ai: None,
-            item: None,
-            always_visible: false,
-            level: 1,
-            equipment: None,
-        }
-    }
-
-    // set the color and then draw the character that represents this object at its position
-    pub fn draw(&self, con: &mut dyn Console) {
-        con.set_default_foreground(self.color);
-        con.put_char(self.x, self.y, self.char, BackgroundFlag::None);
-    }
-
-    pub fn pos(&self) -> (i32, i32) {
-        (self.x, self.y)
-    }
-
-    pub fn set_pos(&mut self, x: i32, y: i32) {
-        self.x = x;
-        self.y = y;
-    }
-
-    // return the distance to another object
-    pub fn distance_to(&self, other: &Object) -> f32 {
-        let dx = other.x - self.x;
-        let dy = other.y - self.y;
-        ((dx.pow(2) + dy.pow(2)) as f32).sqrt()
-    }
-
-    // return the distance to some coord
-    pub fn distance(&self, x: i32, y: i32) -> f32 {
-        (((x - self.x).pow(2) + (y - self.y).pow(2)) as f32).sqrt()
-    }
-
-    pub fn take_damage(&mut self, damage: i32, game: &mut Game) -> Option<i32> {
-        // apply damage if possible
-        if let Some(fighter) = self.fighter.as_mut() {
-            if damage > 0 {
-                fighter.hp -= damage;
-            }
-        }
-        // check for death, call the death function
-        if let Some(fighter) = self.fighter {
-            if fighter.hp <= 0 {
-                self.alive = false;
-                fighter.on_death.callback(self, game);
-                return Some(fighter.xp);
+    // set while the player is being walked somewhere
+    travel: Option<Travel>,
+}
+
+// the game's colors are plain RGB, convert them for drawing
+fn tcod_color(color: roguelike::colors::Color) -> Color {
+    Color::new(color.r, color.g, color.b)
+}
+
+// set the color and then draw the character that represents this object at its position
+fn draw_object(object: &Object, con: &mut dyn Console) {
+    con.set_default_foreground(tcod_color(object.color));
+    con.put_char(object.x, object.y, object.char, BackgroundFlag::None);
+}
+
+impl Ui for Tcod {
+    // return the position of a tile left-clicked in player's FOV (optionally in a
+    // range), or None if right-clicked
+    fn target_tile(
+        &mut self,
+        game: &Game,
+        objects: &[Object],
+        max_range: Option<f32>,
+    ) -> Option<(i32, i32)> {
+        use tcod::input::KeyCode::Escape;
+        loop {
+            // render the screen; this erases inventory and shows the names of the
+            // objects under the mouse
+            self.root.flush();
+            let event = input::check_for_event(input::KEY_PRESS | input::MOUSE).map(|e| e.1);
+            match event {
+                Some(Event::Mouse(m)) => self.mouse = m,
+                Some(Event::Key(k)) => self.key = k,
+                None => self.key = Default::default(),
             }
-        }
-        None
-    }
-
-    pub fn attack(&mut self, target: &mut Object, game: &mut Game) {
-        // a simple formula for attack damage
-        let damage = self.power(game) - target.defense(game);
-        if damage > 0 {
-            // make the target take some damage
-            game.messages.add(
-                format!(
-                    "{} attacks {} for {} hit points.",
-                    self.name, target.name, damage
-                ),
-                WHITE,
-            );
-            if let Some(xp) = target.take_damage(damage, game) {
-                // yield xp to the player
-                self.fighter.as_mut().unwrap().xp += xp;
-            }
-        } else {
-            game.messages.add(
-                format!(
-                    "{} attacks {} but it has no effect!",
-                    self.name, target.name
-                ),
-                WHITE,
-            );
-        }
-    }
-
-    pub fn heal(&mut self, amount: i32, game: &Game) {
-        let max_hp = self.max_hp(game);
-        if let Some(ref mut fighter) = self.fighter {
-            fighter.hp += amount;
-            if fighter.hp > max_hp {
-                fighter.hp = max_hp;
-            }
-        }
-    }
-
-    pub fn equip(&mut self, messages: &mut Messages) {
-        if self.item.is_none() {
-            messages.add(
-                format!(
-                    "Can't equip {:?}.", self
-                ),
-                RED,
-            );
-            return;
-        };
-        if let Some(ref mut equipment) = self.equipment {
-            if !equipment.equipped {
-                equipment.equipped = true;
-                messages.add(
-                    format!(
-                        "Equipped {} on {}.", self.name, equipment.slot
-                    ),
-                    LIGHT_GREEN,
-                );
-            }
-        } else {
-            messages.add(
-                format!(
-                    "Can't equip {:?}.", self
-                ),
-                RED,
-            );
-        }
-    }
-
-    pub fn dequip(&mut self, messages: &mut Messages) {
-        if self.item.is_none() {
-            messages.add(
-                format!(
-                    "Can't dequip {:?}.",
-                    self,
-                ),
-                RED,
-            );
-            return;
-        };
-        if let Some(ref mut equipment) = self.equipment {
-            if equipment.equipped {
-                equipment.equipped = false;
-                messages.add(
-                    format!("Dequipped {} from {}.", self.name, equipment.slot),
-                        LIGHT_YELLOW,
-                    );
-            }
-        } else {
-            messages.add(
-                format!(
-                    "Can't dequip {:?}.",
-                    self
-                ),
-                RED,
-            );
-        }
-    }
-
-    pub fn power(&self, game: &Game) -> i32 {
-        let base_power = self.fighter.map_or(0, |f| f.base_power);
-        let bonus: i32 = self
-            .get_all_equipped(game)
-            .iter()
-            .map(|e| e.power_bonus)
-            .sum();
-        base_power + bonus
-    }
-
-    pub fn defense(&self, game: &Game) -> i32 {
-        let base_defense = self.fighter.map_or(0, |f| f.base_defense);
-        let bonus: i32 = self
-            .get_all_equipped(game)
-            .iter()
-            .map(|e| e.defense_bonus)
-            .sum();
-        base_defense + bonus
-    }
-
-    pub fn max_hp(&self, game: &Game) -> i32 {
-        let base_max_hp = self.fighter.map_or(0, |f| f.base_max_hp);
-        let bonus: i32 = self
-            .get_all_equipped(game)
-            .iter()
-            .map(|e| e.max_hp_bonus)
-            .sum();
-        base_max_hp + bonus
-    }
-
-    // returns a list of equipped items
-    pub fn get_all_equipped(&self, game: &Game) -> Vec<Equipment> {
-        if self.name == "Player" {
-            game.inventory
-                .iter()
-                .filter(|item| item.equipment.map_or(false, |e| e.equipped))
-                .map(|item| item.equipment.unwrap())
-                .collect()
-        } else {
-            vec![] // other objects have no equipment
-        }
-    }
-}
-
-// move by the given amount, if the destination is not blocked
-fn move_by(id: usize, dx: i32, dy: i32, map: &Map, objects: &mut [Object]) {
-    let (x, y) = objects[id].pos();
-    if !is_blocked(x + dx, y + dy, map, objects) {
-        objects[id].set_pos(x + dx, y + dy);
-    }
-}
-
-fn move_towards(id: usize, target_x: i32, target_y: i32, map: &Map, objects: &mut [Object]) {
-    // vector from this object to the target, and distance
-    let dx = target_x - objects[id].x;
-    let dy = target_y - objects[id].y;
-    let distance = ((dx.pow(2) + dy.pow(2)) as f32).sqrt();
-
-    // normalize it to length 1 (preserving direction), then round it and
-    // convert to integer so the movement is restricted to the map grid
-    let dx = (dx as f32 / distance).round() as i32;
-    let dy = (dy as f32 / distance).round() as i32;
-    move_by(id, dx, dy, map, objects);
-}
-
-// Mutably borrow two *separate* elements from the given slice.
-// Panics when the indexes are equal or out of bounds.
-fn mut_two<T>(first_index: usize, second_index: usize, items: &mut [T]) -> (&mut T, &mut T) {
-    assert!(first_index != second_index);
-    let split_at_index = cmp::max(first_index, second_index);
-    let (first_slice, second_slice) = items.split_at_mut(split_at_index);
-    if first_index < second_index {
-        (&mut first_slice[first_index], &mut second_slice[0])
-    } else {
-        (&mut second_slice[0], &mut first_slice[second_index])
-    }
-}
-
-fn is_blocked(x: i32, y: i32, map: &Map, objects: &[Object]) -> bool {
-    // first test the map tile
-    if map[x as usize][y as usize].blocked {
-        return true;
-    }
-    // now check for any blocking objects
-    objects
-        .iter()
-        .any(|object| object.blocks && object.pos() == (x, y))
-}
-
-// combat-related properties and methods (monster, player, NPC).
-#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
-struct Fighter {
-    base_max_hp: i32,
-    hp: i32,
-    base_defense: i32,
-    base_power: i32,
-    xp: i32,
-    on_death: DeathCallback,
-}
+            render_all(self, game, objects);
 
-#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
-enum DeathCallback {
-    Player,
-    Monster,
-}
-
-impl DeathCallback {
-    fn callback(self, object: &mut Object, game: &mut Game) {
-        use DeathCallback::*;
-        let callback = match self {
-            Player => player_death,
-            Monster => monster_death,
-        };
-        callback(object, game);
-    }
-}
-
-#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
-enum Ai {
-    Basic,
-    Confused {
-        previous_ai : Box<Ai>,
-        num_turns: i32,
-    },
-}
+            let (x, y) = (self.mouse.cx as i32, self.mouse.cy as i32);
 
-fn ai_take_turn(monster_id: usize, tcod: &Tcod, game: &mut Game, objects: &mut [Object]) {
-    use Ai::*;
-    if let Some(ai) = objects[monster_id].ai.take() {
-        let new_ai = match ai {
-            Basic => ai_basic(monster_id, tcod, game, objects),
-            Confused {
-                previous_ai,
-                num_turns,
-            } => ai_confused(monster_id, tcod, game, objects, previous_ai, num_turns),
-        };
-        objects[monster_id].ai = Some(new_ai);
-    }
-}
-
-fn ai_basic(monster_id: usize, tcod: &Tcod, game: &mut Game, objects: &mut [Object]) -> Ai {
-    // a basic monster takes it turn. if you can see it, it can see you
-    let (monster_x, monster_y) = objects[monster_id].pos();
-    if tcod.fov.is_in_fov(monster_x, monster_y) {
-        if objects[monster_id].distance_to(&objects[PLAYER]) >= 2.0 {
-            // move twd player if far
-            let (player_x, player_y) = objects[PLAYER].pos();
-            move_towards(monster_id, player_x, player_y, &game.map, objects);
-        } else if objects[PLAYER].fighter.map_or(false, |f| f.hp > 0) {
-            // close to player, attack if player is alive
-            let (monster, player) = mut_two(monster_id, PLAYER, objects);
-            monster.attack(player, game);
-        }
-    }
-    Ai::Basic
-}
-
-fn ai_confused(
-    monster_id: usize,
-    _tcod: &Tcod,
-    game: &mut Game,
-    objects: &mut [Object],
-    previous_ai: Box<Ai>,
-    num_turns: i32,
-) -> Ai {
-    if num_turns >= 0 {
-        // confused
-        // move randomly and decrease the number of turns while confused
-        move_by(
-            monster_id,
-            rand::thread_rng().gen_range(-1, 2),
-            rand::thread_rng().gen_range(-1, 2),
-            &game.map,
-            objects,
-        );
-        Ai::Confused {
-            previous_ai: previous_ai,
-            num_turns: num_turns - 1,
-        }
-    } else {
-        // restore the previous AI(this one gets deleted)
-        game.messages.add(
-            format!("The {} is no longer confused", objects[monster_id].name),
-            YELLOW,
-        );
-        *previous_ai
-    }
-}
+            // accept the target if the player clicked in FOV, and in case of a range
+            // check that too
+            let in_fov = (x < MAP_WIDTH) && (y < MAP_HEIGHT) && game.fov.is_in_fov(x, y);
+            let in_range = max_range.is_none_or(|range| objects[PLAYER].distance(x, y) <= range);
 
-fn create_room(room: Rect, map: &mut Map) {
-    // go through the tiles in the rectangle and make them passable
-    for x in (room.x1 + 1)..room.x2 {
-        for y in (room.y1 + 1)..room.y2 {
-            map[x as usize][y as usize] = Tile::empty();
-        }
-    }
-}
-
-fn create_h_tunnel(x1: i32, x2: i32, y: i32, map: &mut Map) {
-    // horizontal tunnel. `min()` and `max()` are used in case `x1 > x2`
-    for x in cmp::min(x1, x2)..(cmp::max(x1, x2) + 1) {
-        map[x as usize][y as usize] = Tile::empty();
-    }
-}
-
-fn create_v_tunnel(y1: i32, y2: i32, x: i32, map: &mut Map) {
-    // vertical tunnel
-    for y in cmp::min(y1, y2)..(cmp::max(y1, y2) + 1) {
-        map[x as usize][y as usize] = Tile::empty();
-    }
-}
-
-#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
-enum Item {
-    Heal,
-    Lightning,
-    Confuse,
-    Fireball,
-    Sword,
-    Shield,
-}
-
-enum UseResult {
-    UsedUp,
-    Cancelled,
-    UsedAndKept,
-}
-
-fn use_item(inventory_id: usize, tcod: &mut Tcod, game: &mut Game, objects: &mut [Object]) {
-    use Item::*;
-    // just call the "use_function" if it is defined
-    if let Some(item) = game.inventory[inventory_id].item {
-        let on_use = match item {
-            Heal => cast_heal,
-            Lightning => cast_lightning,
-            Confuse => cast_confuse,
-            Fireball => cast_fireball,
-            Sword => toggle_equipment,
-            Shield => toggle_equipment,
-        };
-        match on_use(inventory_id, tcod, game, objects) {
-            UseResult::UsedUp => {
-                // destroy after use
-                game.inventory.remove(inventory_id);
-            }
-            UseResult::Cancelled => {
-                // do not use
-                game.messages.add("Cancelled", WHITE);
+            if self.mouse.lbutton_pressed && in_fov && in_range {
+                // used up: don't let the click start travelling there as well
+                self.mouse.lbutton_pressed = false;
+                return Some((x, y));
             }
-            UseResult::UsedAndKept => {
-                // use and dont destroy
-            }
-        }
-    } else {
-        game.messages.add(
-            format!("The {} cannot be used.", game.inventory[inventory_id].name),
-            WHITE,
-        );
-    }
-}
-
-fn toggle_equipment(
-    inventory_id: usize,
-    _tcod: &mut Tcod,
-    game: &mut Game,
-    _objects: &mut [Object],
-) -> UseResult {
-    let equipment = match game.inventory[inventory_id].equipment {
-        Some(equipment) => equipment,
-        None => return UseResult::Cancelled,
-    };
-    if equipment.equipped {
-        game.inventory[inventory_id].dequip(&mut game.messages);
-    } else {
-        if let Some(current) = get_equipped_in_slot(equipment.slot, &game.inventory) {
-            game.inventory[current].dequip(&mut game.messages);
-        }
-        game.inventory[inventory_id].equip(&mut game.messages);
-    }
-    UseResult::UsedAndKept
-}
 
-fn get_equipped_in_slot(slot: Slot, inventory: &[Object]) -> Option<usize> {
-    for (inventory_id, item) in inventory.iter().enumerate() {
-        if item
-            .equipment
-            .as_ref()
-            .map_or(false, |e| e.equipped && e.slot == slot) {
-                return Some(inventory_id);
+            if self.mouse.rbutton_pressed || self.key.code == Escape {
+                return None; // cancel if the player right-clicked or pressed Esc
             }
-    }
-    None
-}
-
-fn cast_heal (
-    _inventory_id: usize,
-    _tcod: &mut Tcod,
-    game: &mut Game,
-    objects: &mut [Object],
-) -> UseResult {
-    // heal the player
-    let player = &mut objects[PLAYER];
-    if let Some(fighter) = player.fighter {
-        if fighter.hp == player.max_hp(game) {
-            game.messages.add("You are already at full health", YELLOW);
-            return UseResult::Cancelled;
         }
-        game.messages
-            .add("Your wounds start to feel better!", LIGHT_VIOLET);
-        player.heal(HEAL_AMOUNT, game);
-        return UseResult::UsedUp;
     }
-    UseResult::Cancelled
-}
 
-fn cast_lightning(
-    _inventory_id: usize,
-    tcod:&mut Tcod,
-    game: &mut Game,
-    objects: &mut [Object],
-) -> UseResult {
-    // find closest enemy in range and FOV and hit it
-    let monster_id = closest_monster(tcod, objects, LIGHTNING_RANGE);
-    if let Some(monster_id) = monster_id {
-        game.messages.add(
-            format!(
-                "A lightning bolt strikes the {} with electrifying power! \
-                It damages for {} HP.",
-                objects[monster_id].name, LIGHTNING_DAMAGE
-            ),
-            LIGHT_BLUE,
-        );
-        if let Some(xp) = objects[monster_id].take_damage(LIGHTNING_DAMAGE, game) {
-            objects[PLAYER].fighter.as_mut().unwrap().xp += xp;
-        }
-        UseResult::UsedUp
-    } else {
-        // no enemies within range
-        game.messages.add(
-            "No enemy found close enough to strike.", RED
-        );
-        UseResult::Cancelled
-    }
-}
-
-fn cast_confuse(
-    _inventory_id: usize,
-    tcod: &mut Tcod,
-    game: &mut Game,
-    objects: &mut [Object],
-) -> UseResult {
-    // ask the player for a target to confuse
-    game.messages.add(
-        "Left-click an enemy to confuse it, or right-click to cancel.",
-        LIGHT_CYAN,
-    );
-
-    // find closest enemey in range and FOV and confuse it
-    let monster_id = target_monster(tcod, game, objects, Some(CONFUSE_RANGE as f32));
-    if let Some(monster_id) = monster_id {
-        let old_ai = objects[monster_id].ai.take().unwrap_or(Ai::Basic);
-        // replace monster ai with confused ai; after some returns return to normal
-        objects[monster_id].ai = Some(Ai::Confused {
-            previous_ai: Box::new(old_ai),
-            num_turns: CONFUSE_NUM_TURNS,
-        });
-        game.messages.add(
-            format!(
-                "The eyes of the {} look vacant, as it stumbles around!",
-                objects[monster_id].name
-            ),
-            PINK,
-        );
-        UseResult::UsedUp
-    } else {
-        // no enemy found within range
-        game.messages.add(
-            "No enemy found close enough to strike.", RED
-        );
-        UseResult::Cancelled
-    }
-}
-
-fn cast_fireball(
-    _inventory_id: usize,
-    tcod: &mut Tcod,
-    game: &mut Game,
-    objects: &mut [Object],
-) -> UseResult {
-    // ask the player for a target tile to throw the fireball at
-    game.messages.add(
-        "Left-click a tile for the Fireball to be cast at, or Right-click to cancel",
-        LIGHT_RED,
-    );
-    
-    let (x, y) = match target_tile(tcod, game, objects, None) {
-        Some(tile_pos) => tile_pos,
-        None => return UseResult::Cancelled,
-    };
-    game.messages.add(
-        format!(
-            "The Fireball explodes, burning everything withint {} tiles!",
-            FIREBALL_RADIUS,
-        ),
-        ORANGE,
-    );
-
-    let mut xp_to_gain = 0;
-    for (id, obj) in objects.iter_mut().enumerate() {
-        if obj.distance(x, y) <= FIREBALL_RADIUS as f32 && obj.fighter.is_some() {
-            game.messages.add(
-                format!(
-                    "The {} gets burned for {} HP.",
-                    obj.name, FIREBALL_DAMAGE
-                ),
-                ORANGE,
+    fn level_up_choice(&mut self, fighter: &Fighter) -> usize {
+        let mut choice = None;
+        while choice.is_none() {
+            // keep asking until a choice is made
+            choice = menu(
+                "Level up! Choose your gift:\n",
+                &[
+                    format!("Constitution (+20 HP, from {})", fighter.base_max_hp),
+                    format!("Might (+1 ATK, from {})", fighter.base_power),
+                    format!("Bulwark (+1 DEF, from {})", fighter.base_defense),
+                ],
+                LEVEL_SCREEN_WIDTH,
+                &mut self.root,
             );
-            if let Some(xp) = obj.take_damage(FIREBALL_DAMAGE, game) {
-                if id != PLAYER {  
-                    // no reward for self immolation
-                    xp_to_gain += xp;
-                }
-            }
-        }
-    }
-    objects[PLAYER].fighter.as_mut().unwrap().xp += xp_to_gain;
-    UseResult::UsedUp
-}
-
-// find closest enemy, upto a max range and in the player FOV
-fn closest_monster(tcod: &Tcod, objects: &[Object], max_range: i32) -> Option<usize> {
-    let mut closest_enemy = None;
-    let mut closest_dist = (max_range + 1) as f32;
-
-    for (id, object) in objects.iter().enumerate() {
-        if (id != PLAYER)
-            && object.fighter.is_some()
-            && object.ai.is_some()
-            && tcod.fov.is_in_fov(object.x, object.y)
-        {
-            // calc the dist bw obj and player
-            let dist = objects[PLAYER].distance_to(object);
-            if dist < closest_dist {
-                // close, remember it
-                closest_enemy = Some(id);
-                closest_dist = dist;
-            }
-        }
-    }
-    closest_enemy
-}
-
-// return the position of a tile left-clicked in player's FOV (optionally in a 
-// range), or a (None, None) if right-clicked
-fn target_tile(
-    tcod: &mut Tcod,
-    game: &mut Game,
-    objects: &mut [Object],
-    max_range: Option<f32>,
-) -> Option<(i32, i32)> {
-    use tcod::input::KeyCode::Escape;
-    loop {
-        // render the screen; this erases inventory and shows the names of the 
-        // objects under the mouse
-        tcod.root.flush();
-        let event = input::check_for_event(input::KEY_PRESS | input::MOUSE).map(|e| e.1);
-        match event {
-            Some(Event::Mouse(m)) => tcod.mouse = m,
-            Some(Event::Key(k)) => tcod.key = k,
-            None => tcod.key = Default::default(),
-        }
-        render_all(tcod, game, objects, false);
-
-        let (x, y) = (tcod.mouse.cx as i32, tcod.mouse.cy as i32);
-
-        // accept the target if the player clicked in FOV, and in case of a range
-        // check that too
-        let in_fov = (x < MAP_WIDTH) && (y < MAP_HEIGHT) && tcod.fov.is_in_fov(x, y);
-        let in_range = max_range.map_or(true, |range| objects[PLAYER].distance(x, y) <= range);
-
-        if tcod.mouse.lbutton_pressed && in_fov && in_range {
-            return Some((x, y));
-        }
-
-        if tcod.mouse.rbutton_pressed || tcod.key.code == Escape {
-            return None; // cancel if the player right-clicked or pressed Esc
         }
+        choice.unwrap()
     }
 }
 
-// returns a clicked monster inside FOV up to a range, or None if 
-// right-clicked
-fn target_monster(
-    tcod: &mut Tcod,
-    game: &mut Game,
-    objects: &mut [Object],
-    max_range: Option<f32>,
-) -> Option<usize> {
-    loop {
-        match target_tile(tcod, game, objects, max_range) {
-            Some((x, y)) => {
-                // return the first clicked monster, otherwise keep looping
-                for (id, obj) in objects.iter().enumerate() {
-                    if obj.pos() == (x, y) && obj.fighter.is_some() && id != PLAYER {
-                        return Some(id);
-                    }
-                }
-            }
-            None => return None,
-        }
-    }
-}
-
-// An object that can be equipped
-#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
-struct Equipment {
-    slot: Slot,
-    equipped: bool,
-    max_hp_bonus: i32,
-    power_bonus: i32,
-    defense_bonus: i32,
-}
-
-#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
-enum Slot {
-    LeftHand,
-    RightHand,
-    Head,
-}
-
-impl std::fmt::Display for Slot {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        match *self {
-            Slot::LeftHand => write!(f, "Left Hand"),
-            Slot::RightHand => write!(f, "Right Hand"),
-            Slot::Head => write!(f, "Head"),
-        }
-    }
-}
-
-fn make_map(objects: &mut Vec<Object>, level: u32) -> Map {
-    // fill map with "blocked" tiles
-    let mut map = vec![vec![Tile::wall(); MAP_HEIGHT as usize]; MAP_WIDTH as usize];
-
-    // Player is the first element, remove everything else
-    // NOTE: works only when the player is the first object!
-    assert_eq!(&objects[PLAYER] as *const _, &objects[0] as *const _);
-    objects.truncate(1);
-
-    let mut rooms = vec![];
-
-    for _ in 0..MAX_ROOMS {
-        // random width and height
-        let w = rand::thread_rng().gen_range(ROOM_MIN_SIZE, ROOM_MAX_SIZE + 1);
-        let h = rand::thread_rng().gen_range(ROOM_MIN_SIZE, ROOM_MAX_SIZE + 1);
-        // random position without going out of the boundaries of the map
-        let x = rand::thread_rng().gen_range(0, MAP_WIDTH - w);
-        let y = rand::thread_rng().gen_range(0, MAP_HEIGHT - h);
-
-        let new_room = Rect::new(x, y, w, h);
-
-        // run through the other rooms and see if they intersect with this one
-        let failed = rooms
-            .iter()
-            .any(|other_room| new_room.intersects_with(other_room));
-
-        if !failed {
-            // this means there are no intersections, so this room is valid
-
-            // "paint" it to the map's tiles
-            create_room(new_room, &mut map);
-
-            // add some content to this room, such as monsters
-            place_objects(new_room, &map, objects, level);
-
-            // center coordinates of the new room, will be useful later
-            let (new_x, new_y) = new_room.center();
-
-            if rooms.is_empty() {
-                // this is the first room, where the player starts at
-                objects[PLAYER].set_pos(new_x, new_y);
-            } else {
-                // all rooms after the first:
-                // connect it to the previous room with a tunnel
-
-                // center coordinates of the previous room
-                let (prev_x, prev_y) = rooms[rooms.len() - 1].center();
-
-                // toss a coin (random bool value -- either true or false)
-                if rand::random() {
-                    // first move horizontally, then vertically
-                    create_h_tunnel(prev_x, new_x, prev_y, &mut map);
-                    create_v_tunnel(prev_y, new_y, new_x, &mut map);
-                } else {
-                    // first move vertically, then horizontally
-                    create_v_tunnel(prev_y, new_y, prev_x, &mut map);
-                    create_h_tunnel(prev_x, new_x, new_y, &mut map);
-                }
-            }
-
-            // finally, append the new room to the list
-            rooms.push(new_room);
-        }
-    }
-    // create stairs at the center of the last room
-    let (last_room_x, last_room_y) = rooms[rooms.len() -1].center();
-    let mut stairs = Object::new(last_room_x, last_room_y, '<', "stairs", WHITE, false);
-    stairs.always_visible = true;
-    objects.push(stairs);
-
-    map
-}
-struct Transition {
-    level: u32,
-    value: u32,
-}
-
-// returns a value that depends on level. the table specifies what value
-// occurs after each level, default is 0
-fn from_dungeon_level(table: &[Transition], level: u32) -> u32 {
-    table
-        .iter()
-        .rev()
-        .find(|transition| level >= transition.level)
-        .map_or(0, |transition| transition.value)
-}
-
-fn place_objects(room: Rect, map: &Map, objects: &mut Vec<Object>, level: u32) {
-    // max no of monsters per room
-    let max_monsters = from_dungeon_level(
-        &[
-            Transition { level: 1, value: 2 },
-            Transition { level: 4, value: 3 },
-            Transition { level: 6, value: 5 },
-        ],
-        level,
-    );
-
-    // choose random number of monsters
-    let num_monsters = rand::thread_rng().gen_range(0, max_monsters + 1);
-
-    let troll_chance = from_dungeon_level(
-        &[
-            Transition {
-                level: 3,
-                value: 15,
-            },
-            Transition {
-                level: 5,
-                value: 30,
-            },
-            Transition {
-                level: 7,
-                value: 30,
-            },
-        ],
-        level,
-    );
-
-    // monster random table
-    let monster_chances = &mut [
-        Weighted {
-            weight: 80,
-            item: "Orc",
-        },
-        Weighted {
-            weight: troll_chance,
-            item: "Troll",
-        },
-    ];
-    let monster_choice = WeightedChoice::new(monster_chances);
-
-    for _ in 0..num_monsters {
-        // choose random spot for this monster
-        let x = rand::thread_rng().gen_range(room.x1 + 1, room.x2);
-        let y = rand::thread_rng().gen_range(room.y1 + 1, room.y2);
-
-        // only place it if the tile is not blocked
-        if !is_blocked(x, y, map, objects) {
-            let mut monster = match monster_choice.ind_sample(&mut rand::thread_rng()) {
-                "Orc" => {
-                    // create an orc
-                    let mut orc = Object::new(x, y, 'o', "Orc", DESATURATED_GREEN, true);
-                    orc.fighter = Some(Fighter {
-                        base_max_hp: 20,
-                        hp: 20,
-                        base_defense: 0,
-                        base_power: 4,
-                        xp: 35,
-                        on_death: DeathCallback::Monster,
-                    });
-                    orc.ai = Some(Ai::Basic);
-                    orc
-                }
-                "Troll" => {
-                    // create a troll
-                    let mut troll = Object::new(x, y, 'T', "Troll", DARKER_GREEN, true);
-                    troll.fighter = Some(Fighter {
-                        base_max_hp: 30,
-                        hp: 30,
-                        base_defense: 2,
-                        base_power: 8,
-                        xp: 100,
-                        on_death: DeathCallback::Monster,
-                    });
-                    troll.ai = Some(Ai::Basic);
-                    troll
-                }
-                _ => unreachable!(),
-            };
-            monster.alive = true;
-            objects.push(monster);
-        }
-    }
-
-    // maximum no of items per room
-    let max_items = from_dungeon_level(
-        &[
-            Transition { level: 1, value: 1 },
-            Transition { level: 4, value: 2},
-        ],
-        level,
-    );
-
-    // item random table
-    let item_chances = &mut [
-        // sword
-        Weighted {
-            weight: from_dungeon_level(
-                &[Transition { level: 4, value: 5 }],
-                level
-            ),
-            item: Item::Sword,
-        },
-
-        //shield
-        Weighted {
-            weight: from_dungeon_level(
-                &[Transition { level: 8, value: 15 }],
-                level
-            ),
-            item: Item::Shield,
-        },
-
-        // healing potion always present
-        Weighted {
-            weight: 35,
-            item: Item::Heal,
-        },
-
-        Weighted {
-            weight: from_dungeon_level(
-                &[Transition {
-                    level: 4,
-                    value: 25,
-                }],
-                level,
-            ),
-            item: Item::Lightning,
-        },
-
-        Weighted {
-            weight: from_dungeon_level(
-                &[Transition {
-                    level: 6,
-                    value: 25,
-                }],
-                level,
-            ),
-            item: Item::Fireball,
-        },
-
-        Weighted {
-            weight: from_dungeon_level(
-                &[Transition {
-                    level: 2,
-                    value: 10,
-                }],
-                level,
-            ),
-            item: Item::Confuse,
-        },
-    ];
-    let item_choice = WeightedChoice::new(item_chances);
-
-    // choose random number of items
-    let num_items = rand::thread_rng().gen_range(0, max_items + 1);
-
-    for _ in 0..num_items {
-        // choose random spot for this item
-        let x = rand::thread_rng().gen_range(room.x1 + 1, room.x2);
-        let y = rand::thread_rng().gen_range(room.y1 + 1, room.y2);
-
-        //only place if the tile is not blocked
-        if !is_blocked(x, y, map, objects) {
-            let mut item = match item_choice.ind_sample(&mut rand::thread_rng()) {
-                Item::Sword => {
-                    // create a sword
-                    let mut object = Object::new(
-                        x,
-                        y,
-                        '/',
-                        "Sword",
-                        SKY,
-                        false,
-                    );
-                    object.item = Some(Item::Sword);
-                    object.equipment = Some(Equipment{
-                        equipped: false,
-                        slot: Slot::RightHand,
-                        power_bonus: 3,
-                        defense_bonus: 0,
-                        max_hp_bonus: 0,
-                    });
-                    object
-                }
-                Item::Shield => {
-                    // create a shield
-                    let mut object = Object::new(
-                        x,
-                        y,
-                        '[',
-                        "Shield",
-                        DARKER_ORANGE,
-                        false
-                    );
-                    object.item = Some(Item::Shield);
-                    object.equipment = Some(Equipment {
-                        equipped: false,
-                        slot: Slot::LeftHand,
-                        max_hp_bonus: 0,
-                        defense_bonus: 1,
-                        power_bonus: 0,
-                    });
-                    object
-                }
-                Item::Heal => {
-                    // create a healing potion
-                    let mut object = Object::new(
-                        x, 
-                        y, 
-                        '!', 
-                        "Healing Potion", 
-                        VIOLET, 
-                        false
-                    );
-                    object.item = Some(Item::Heal);
-                    object
-                }
-                Item::Lightning => {
-                    // create a lightning bolt scroll
-                    let mut object = Object::new(
-                        x,
-                        y,
-                        '#',
-                        "Scroll of Lightning",
-                        LIGHT_BLUE,
-                        false,
-                    );
-                    object.item = Some(Item::Lightning);
-                    object
-                }
-                Item::Fireball => {
-                    // create a fireball scroll
-                    let mut object = Object::new(
-                        x, 
-                        y, 
-                        '#', 
-                        "Scroll of Fire",
-                        LIGHT_RED,
-                        false,
-                    );
-                    object.item = Some(Item::Fireball);
-                    object
-                }
-                Item::Confuse => {
-                    // create a confuse scroll
-                    let mut object = Object::new(
-                        x, 
-                        y, 
-                        '#', 
-                        "Scroll of Confusion", 
-                        PINK, 
-                        false
-                    );
-                    object.item = Some(Item::Confuse);
-                    object
-                }
-            };
-            item.always_visible = true;
-            objects.push(item);
-        }
-    }
-}
-
-fn render_bar (
+#[allow(clippy::too_many_arguments)]
+fn render_bar(
     panel: &mut Offscreen,
     x: i32,
     y: i32,
@@ -1285,7 +173,7 @@ fn render_bar (
         y,
         BackgroundFlag::None,
         TextAlignment::Center,
-        &format!("{}: {}/{}", name, value, maximum),   
+        format!("{}: {}/{}", name, value, maximum),
     );
 }
 
@@ -1301,18 +189,11 @@ fn get_names_under_mouse(mouse: Mouse, objects: &[Object], fov_map: &FovMap) ->
     names.join(", ") //join names seperated by commas
 }
 
-fn render_all(tcod: &mut Tcod, game: &mut Game, objects: &[Object], fov_recompute: bool) {
-    if fov_recompute {
-        // recompute FOV if needed (the player moved or something)
-        let player = &objects[PLAYER];
-        tcod.fov
-            .compute_fov(player.x, player.y, TORCH_RADIUS, FOV_LIGHT_WALLS, FOV_ALGO);
-    }
-
+fn render_all(tcod: &mut Tcod, game: &Game, objects: &[Object]) {
     // go through all tiles, and set their background color
     for y in 0..MAP_HEIGHT {
         for x in 0..MAP_WIDTH {
-            let visible = tcod.fov.is_in_fov(x, y);
+            let visible = game.fov.is_in_fov(x, y);
             let wall = game.map[x as usize][y as usize].block_sight;
             let color = match (visible, wall) {
                 // outside of field of view:
@@ -1323,32 +204,32 @@ fn render_all(tcod: &mut Tcod, game: &mut Game, objects: &[Object], fov_recomput
                 (true, false) => COLOR_LIGHT_GROUND,
             };
 
-            let explored = &mut game.map[x as usize][y as usize].explored;
-            if visible {
-                // since it's visible, explore it
-                *explored = true;
-            }
-            if *explored {
-                // show explored tiles only (any visible tile is explored already)
+            // show explored tiles only (any visible tile is explored already)
+            let tile = &game.map[x as usize][y as usize];
+            if tile.explored {
                 tcod.con
                     .set_char_background(x, y, color, BackgroundFlag::Set);
+                match tile.door {
+                    Some(Door::Open) => tcod.con.put_char_ex(x, y, '\'', COLOR_DOOR, color),
+                    Some(_) => tcod.con.put_char_ex(x, y, '+', COLOR_DOOR, color),
+                    None => {}
+                }
             }
         }
     }
 
     let mut to_draw: Vec<_> = objects
         .iter()
-        .filter(|o| 
-            {
-                tcod.fov.is_in_fov(o.x, o.y) || (o.always_visible && game.map[o.x as usize][o.y as usize].explored)
-            }
-        )
+        .filter(|o| {
+            game.fov.is_in_fov(o.x, o.y)
+                || (o.always_visible && game.map[o.x as usize][o.y as usize].explored)
+        })
         .collect();
     // sort so that non-blocking objects come first
-    to_draw.sort_by(|o1, o2| o1.blocks.cmp(&o2.blocks));
+    to_draw.sort_by_key(|o| o.blocks);
     // draw the objects in the list
     for object in &to_draw {
-        object.draw(&mut tcod.con);
+        draw_object(object, &mut tcod.con);
     }
 
     // blit the contents of "con" to the root console
@@ -1374,7 +255,7 @@ fn render_all(tcod: &mut Tcod, game: &mut Game, objects: &[Object], fov_recomput
         if  y < 0 {
             break;
         }
-        tcod.panel.set_default_foreground(color);
+        tcod.panel.set_default_foreground(tcod_color(color));
         tcod.panel.print_rect(MSG_X, y, MSG_WIDTH, 0, msg);
     }
 
@@ -1399,6 +280,18 @@ fn render_all(tcod: &mut Tcod, game: &mut Game, objects: &[Object], fov_recomput
         TextAlignment::Left,
         format!("Dungeon Level: {}", game.dungeon_level),
     );
+    // and whatever's wrong (or right) with them, as many as fit under it
+    let rows = 4..PANEL_HEIGHT;
+    for (y, status) in rows.zip(&objects[PLAYER].statuses) {
+        tcod.panel.set_default_foreground(tcod_color(status.kind.color()));
+        tcod.panel.print_ex(
+            1,
+            y,
+            BackgroundFlag::None,
+            TextAlignment::Left,
+            format!("{} ({})", status.kind.name(), status.turns),
+        );
+    }
 
     // display the names of the objects under the mouse
     tcod.panel.set_default_foreground(LIGHT_GREY);
@@ -1407,7 +300,7 @@ fn render_all(tcod: &mut Tcod, game: &mut Game, objects: &[Object], fov_recomput
         0,
         BackgroundFlag::None,
         TextAlignment::Left,
-        get_names_under_mouse(tcod.mouse, objects, &tcod.fov),
+        get_names_under_mouse(tcod.mouse, objects, &game.fov),
     );
 
     // blit the contents of `panel` to the root console
@@ -1422,75 +315,6 @@ fn render_all(tcod: &mut Tcod, game: &mut Game, objects: &[Object], fov_recomput
     );
 }
 
-fn player_move_or_attack(dx: i32, dy: i32, game: &mut Game, objects: &mut [Object]) {
-    // the coordinates the player is moving to/attacking
-    let x = objects[PLAYER].x + dx;
-    let y = objects[PLAYER].y + dy;
-
-    // try to find an attackable object there
-    let target_id = objects
-        .iter()
-        .position(|object| object.fighter.is_some() && object.pos() == (x, y));
-
-    // attack if target found, move otherwise
-    match target_id {
-        Some(target_id) => {
-            let (player, target) = mut_two(PLAYER, target_id, objects);
-            player.attack(target, game);
-        }
-        None => {
-            move_by(PLAYER, dx, dy, &game.map, objects);
-        }
-    }
-}
-
-fn pick_item_up(object_id: usize, game: &mut Game, objects: &mut Vec<Object>) {
-    if game.inventory.len() >= 26 {
-        game.messages.add(
-            format!(
-                "Your invectory is full, cannot pick up {}.",
-                objects[object_id].name
-            ),
-            RED,
-        );
-    } else {
-        let item = objects.swap_remove(object_id);
-        game.messages   
-            .add(
-                format!(
-                    "You picked up a {}!", 
-                    item.name
-                ), 
-                GREEN,
-            );
-        let index = game.inventory.len();
-        let slot = item.equipment.map(|e| e.slot);
-        game.inventory.push(item);
-
-        // automatically equip, if the corresponding slot is empty
-        if let Some(slot) = slot {
-            if get_equipped_in_slot(slot, &game.inventory).is_none() {
-                game.inventory[index].equip(&mut game.messages);
-            }
-        }
-    }
-}
-
-fn drop_item(inventory_id: usize, game: &mut Game, objects: &mut Vec<Object>) {
-    let mut item = game.inventory.remove(inventory_id);
-    if item.equipment.is_some() {
-        item.dequip(&mut game.messages);
-    }
-    item.set_pos(objects[PLAYER].x, objects[PLAYER].y);
-    game.messages.add(
-        format!(
-            "You dropped a {}.",
-            item.name
-        ),
-        YELLOW
-    );
-}
-
 fn menu<T: AsRef<str>>(header: &str, options: &[T], width: i32, root: &mut Root) -> Option<usize> {
     assert!(
         options.len() <= 26,
@@ -1557,7 +381,7 @@ fn menu<T: AsRef<str>>(header: &str, options: &[T], width: i32, root: &mut Root)
 
 fn inventory_menu(inventory: &[Object], header: &str, root: &mut Root) -> Option<usize> {
     // how a menu with each item of the inv as an option
-    let options = if inventory.len() == 0 {
+    let options = if inventory.is_empty() {
         vec!["Inventory is empty.".into()]
     } else {
         inventory
@@ -1568,6 +392,7 @@ fn inventory_menu(inventory: &[Object], header: &str, root: &mut Root) -> Option
                     Some(equipment) if equipment.equipped => {
                         format!("{} (on {})", item.name, equipment.slot)
                     }
+                    _ if item.count > 1 => format!("{} ({})", item.name, item.count),
                     _ => item.name.clone(),
                 }
             })
@@ -1577,7 +402,7 @@ fn inventory_menu(inventory: &[Object], header: &str, root: &mut Root) -> Option
     let inventory_index  = menu(header, &options, INVENTORY_WIDTH, root);
 
     // if an item was chosen, return it
-    if inventory.len() > 0 {
+    if !inventory.is_empty() {
         inventory_index
     } else {
         None
@@ -1589,13 +414,63 @@ fn msgbox(text: &str, width: i32, root: &mut Root) {
     menu(text, options, width, root);
 }
 
+// let the player type in a dungeon seed; None if cancelled or left empty
+fn seed_prompt(width: i32, root: &mut Root) -> Option<u64> {
+    use tcod::input::KeyCode::{Backspace, Enter, Escape};
+    let mut digits = String::new();
+    loop {
+        let text = format!("Type a dungeon seed and press Enter:\n\n{}_", digits);
+        let height = root.get_height_rect(0, 0, width, SCREEN_HEIGHT, &text);
+        let mut window = Offscreen::new(width, height);
+        window.set_default_foreground(WHITE);
+        window.print_rect_ex(
+            0,
+            0,
+            width,
+            height,
+            BackgroundFlag::None,
+            TextAlignment::Left,
+            &text,
+        );
+        let x = SCREEN_WIDTH / 2 - width / 2;
+        let y = SCREEN_HEIGHT / 2 - height / 2;
+        blit(&window, (0, 0), (width, height), root, (x, y), 1.0, 0.7);
+        root.flush();
+
+        let key = root.wait_for_keypress(true);
+        match key.code {
+            Enter => return digits.parse().ok(),
+            Escape => return None,
+            Backspace => {
+                digits.pop();
+            }
+            // 19 digits always fit in a u64
+            _ if key.printable.is_ascii_digit() && digits.len() < 19 => digits.push(key.printable),
+            _ => {}
+        }
+    }
+}
+
 fn handle_keys(tcod: &mut Tcod, game: &mut Game, objects: &mut Vec<Object>) -> PlayerAction {
-    // use tcod::input::Key;
     use tcod::input::KeyCode::*;
     use PlayerAction::*;
 
+    // work out what the player asked for; keys that only change what is shown
+    // return straight away, everything else becomes a command for the game
     let player_alive = objects[PLAYER].alive;
-    match (tcod.key, tcod.key.text(), player_alive) {
+
+    // left-click on a tile the player has seen: walk there
+    if tcod.mouse.lbutton_pressed && player_alive {
+        // the click is handled now, don't see it again next frame
+        tcod.mouse.lbutton_pressed = false;
+        let (x, y) = (tcod.mouse.cx as i32, tcod.mouse.cy as i32);
+        if x < MAP_WIDTH && y < MAP_HEIGHT && game.map[x as usize][y as usize].explored {
+            tcod.travel = Travel::start(Destination::Tile(x, y), game, objects);
+            return DidntTakeTurn;
+        }
+    }
+
+    let command = match (tcod.key, tcod.key.text(), player_alive) {
         (
             Key {
                 code: Enter,
@@ -1608,57 +483,30 @@ fn handle_keys(tcod: &mut Tcod, game: &mut Game, objects: &mut Vec<Object>) -> P
             // Alt+Enter: toggle fullscreen
             let fullscreen = tcod.root.is_fullscreen();
             tcod.root.set_fullscreen(!fullscreen);
-            DidntTakeTurn
+            return DidntTakeTurn;
         }
-        (Key { code: Escape, .. }, _, _) => Exit, // exit game
+        (Key { code: Escape, .. }, _, _) => return Exit, // exit game
 
         // movement keys
         (Key { code: Up, .. }, _, true) | (Key { code: NumPad8, .. }, _, true) => {
-            player_move_or_attack(0, -1, game, objects);
-            TookTurn
+            Command::Move(0, -1)
         }
         (Key { code: Down, .. }, _, true) | (Key { code: NumPad2, .. }, _, true) => {
-            player_move_or_attack(0, 1, game, objects);
-            TookTurn
+            Command::Move(0, 1)
         }
         (Key { code: Left, .. }, _, true) | (Key { code: NumPad4, .. }, _, true) => {
-            player_move_or_attack(-1, 0, game, objects);
-            TookTurn
+            Command::Move(-1, 0)
         }
         (Key { code: Right, .. }, _, true) | (Key { code: NumPad6, .. }, _, true) => {
-            player_move_or_attack(1, 0, game, objects);
-            TookTurn
-        }
-        (Key { code: NumPad7, .. }, _, true) => {
-            player_move_or_attack(-1, -1, game, objects);
-            TookTurn
-        }
-        (Key { code: NumPad9, .. }, _, true) => {
-            player_move_or_attack(1, -1, game, objects);
-            TookTurn
-        }
-        (Key { code: NumPad1, .. }, _, true) => {
-            player_move_or_attack(-1, 1, game, objects);
-            TookTurn
-        }
-        (Key { code: NumPad3, .. }, _, true) => {
-            player_move_or_attack(1, 1, game, objects);
-            TookTurn
-        }
-        (Key { code: NumPad0, .. }, _, true) => {
-            TookTurn // wait
+            Command::Move(1, 0)
         }
+        (Key { code: NumPad7, .. }, _, true) => Command::Move(-1, -1),
+        (Key { code: NumPad9, .. }, _, true) => Command::Move(1, -1),
+        (Key { code: NumPad1, .. }, _, true) => Command::Move(-1, 1),
+        (Key { code: NumPad3, .. }, _, true) => Command::Move(1, 1),
+        (Key { code: NumPad0, .. }, _, true) => Command::Wait,
 
-        (Key { code: Text, ..}, "g", true) => {
-            // pick up an item
-            let item_id = objects
-                .iter()
-                .position(|object| object.pos() == objects[PLAYER].pos() && object.item.is_some());
-            if let Some(item_id) = item_id {
-                pick_item_up(item_id, game, objects);
-            }
-            DidntTakeTurn
-        }
+        (Key { code: Text, .. }, "g", true) => Command::PickUp, // pick up an item
 
         (Key { code: Text, .. }, "d", true) => {
             // show the inventory; if an item is selected, drop it
@@ -1667,10 +515,10 @@ fn handle_keys(tcod: &mut Tcod, game: &mut Game, objects: &mut Vec<Object>) -> P
                 "Press the key next to an item to drop it, or any other to cancel. \n",
                 &mut tcod.root,
             );
-            if let Some(inventory_index) = inventory_index {
-                drop_item(inventory_index, game, objects);
+            match inventory_index {
+                Some(inventory_index) => Command::Drop(inventory_index),
+                None => return DidntTakeTurn,
             }
-            DidntTakeTurn
         }
 
         (Key { code: Text,..}, "i", true) => {
@@ -1680,21 +528,41 @@ fn handle_keys(tcod: &mut Tcod, game: &mut Game, objects: &mut Vec<Object>) -> P
                 "Press the key next to an item to use it, or any other to cancel.\n",
                 &mut tcod.root,
             );
-            if let Some(inventory_index) = inventory_index {
-                use_item(inventory_index, tcod, game, objects);
+            match inventory_index {
+                Some(inventory_index) => Command::Use(inventory_index),
+                None => return DidntTakeTurn,
             }
-            DidntTakeTurn
         }
 
-        (Key { code: Text, .. }, "<", true) => {
-            // go down stairs, if the player is standing on them
-            let player_on_stairs = objects
-                .iter()
-                .any(|object| object.pos() == objects[PLAYER].pos() && object.name == "stairs");
-            if player_on_stairs {
-                next_level(tcod, game, objects);
+        (Key { code: Text, .. }, "t", true) => {
+            // show the inventory; if an item is selected, throw it
+            let inventory_index = inventory_menu(
+                &game.inventory,
+                "Press the key next to an item to throw it, or any other to cancel.\n",
+                &mut tcod.root,
+            );
+            match inventory_index {
+                Some(inventory_index) => Command::Throw(inventory_index),
+                None => return DidntTakeTurn,
             }
-            DidntTakeTurn
+        }
+
+        (Key { code: Text, .. }, "<", true) => Command::Descend, // go down stairs
+        (Key { code: Text, .. }, ">", true) => Command::Ascend, // go up stairs
+
+        (Key { code: Text, .. }, "C", true) => Command::CloseDoor, // close a door
+
+        (Key { code: Text, .. }, "f", true) => Command::Fire, // shoot at a monster
+
+        (Key { code: Text, .. }, "o", true) => {
+            // explore on auto-pilot; the steps are taken in `play_game`
+            tcod.travel = Travel::start(Destination::Unexplored, game, objects);
+            return DidntTakeTurn;
+        }
+        (Key { code: Text, .. }, "s", true) => {
+            // walk to the stairs
+            tcod.travel = Travel::start(Destination::Stairs, game, objects);
+            return DidntTakeTurn;
         }
 
         (Key { code: Text, .. }, "c", true) => {
@@ -1712,113 +580,57 @@ fn handle_keys(tcod: &mut Tcod, game: &mut Game, objects: &mut Vec<Object>) -> P
         
         Max HP: {}
         ATK: {}
-        DEF: {}",
-                    level, fighter.xp, level_up_xp, player.max_hp(game), player.power(game), player.defense(game)
+        DEF: {}
+        DMG: {}
+        ACC: {}
+        EVA: {}",
+                    level, fighter.xp, level_up_xp, player.max_hp(game), player.power(game), player.defense(game),
+                    player.melee_damage(game), player.accuracy(), player.evasion()
                 );
                 msgbox(&msg, CHARACTER_SCREEN_WIDTH, &mut tcod.root);
             }
-        
-            DidntTakeTurn
-        }
-
-        _ => DidntTakeTurn,
-    }
-}
 
-#[derive(Clone, Copy, Debug, PartialEq)]
-enum PlayerAction {
-    TookTurn,
-    DidntTakeTurn,
-    Exit,
-}
-
-fn player_death(player: &mut Object, game: &mut Game) {
-    game.messages.add("You died!", RED);
-    player.char = '%';
-    player.color = DARK_RED;
-}
-
-fn monster_death(monster: &mut Object, game: &mut Game) {
-    game.messages.add(
-            format!(
-                "The {} is dead! You gained {} EXP.", 
-                monster.name, 
-                monster.fighter.unwrap().xp,
-        ),
-        ORANGE,
-    );
-    monster.char = '%';
-    monster.color = DARK_RED;
-    monster.blocks = false;
-    monster.fighter = None;
-    monster.ai = None;
-    monster.name = format!("remains of {}", monster.name);
-}
-
-fn new_game(tcod: &mut Tcod) -> (Game, Vec<Object>) {
-    // create object representing the player
-    let mut player = Object::new(0, 0, '@', "Player", BLACK, true);
-    player.alive = true;
-    player.fighter = Some(Fighter {
-        base_max_hp: 100,
-        hp: 100,
-        base_defense: 1,
-        base_power: 2,
-        xp: 0,
-        on_death: DeathCallback::Player, //<1>
-    });
+            return DidntTakeTurn;
+        }
 
-    // the list of objects with just the player
-    let mut objects = vec![player];
+        (Key { code: Text, .. }, "L", _) => {
+            // show the rolls behind the latest attacks, oldest first
+            let mut log: Vec<&str> = game
+                .combat_log
+                .iter()
+                .rev()
+                .take(COMBAT_LOG_LINES)
+                .map(|(message, _)| message.as_str())
+                .collect();
+            log.reverse();
+            let msg = format!("Combat log\n\n{}", log.join("\n"));
+            msgbox(&msg, COMBAT_LOG_WIDTH, &mut tcod.root);
+            return DidntTakeTurn;
+        }
 
-    let mut game = Game {
-        // generate map (not drawn to the screen)
-        map: make_map(&mut objects, 1),
-        messages: Messages::new(),
-        inventory: vec![],
-        dungeon_level: 1,
+        _ => return DidntTakeTurn,
     };
-
-    let mut dagger = Object::new(0, 0, '~', "Dagger", SKY, false);
-    dagger.item = Some(Item::Sword);
-    dagger.equipment = Some(Equipment {
-        equipped: true,
-        slot: Slot::RightHand,
-        max_hp_bonus: 0,
-        defense_bonus: 0,
-        power_bonus: 2,
-    });
-    game.inventory.push(dagger);
-
-    initialise_fov(tcod, &game.map);
-
-    game.messages.add(
-        "Welcome stranger! Prepare to perish in The Dungeons of Alakazam.",
-        YELLOW,
-    );
-
-    (game, objects)
+    play_command(command, tcod, game, objects)
 }
 
-fn initialise_fov(tcod: &mut Tcod, map: &Map) {
-    // create FOV map, according to the generated map
-    for y in 0..MAP_HEIGHT {
-        for x in 0..MAP_WIDTH {
-            tcod.fov.set(
-                x,
-                y,
-                !map[x as usize][y as usize].block_sight,
-                !map[x as usize][y as usize].blocked,
-            );
-        }
-    }
+fn initialise_map_console(tcod: &mut Tcod) {
     // unexplored areas start black (which is the default bg color)
     tcod.con.clear();
 }
 
 fn play_game(tcod: &mut Tcod, game: &mut Game, objects: &mut Vec<Object>) {
-    // force FOV "recompute" first time through the game loop
-    let mut previous_player_position = (-1, -1);
+    // record everything the player does, so the game can be replayed. not
+    // being able to write it down is no reason to stop playing
+    let mut recorder = match ReplayWriter::create(REPLAY_FILE, game) {
+        Ok(recorder) => Some(recorder),
+        Err(e) => {
+            game.messages.add(
+                format!("This game isn't being recorded: {}", e),
+                roguelike::colors::ORANGE,
+            );
+            None
+        }
+    };
 
     while !tcod.root.window_closed() {
         // clear the screen of the previous frame
@@ -1830,118 +642,83 @@ fn play_game(tcod: &mut Tcod, game: &mut Game, objects: &mut Vec<Object>) {
             _ => tcod.key = Default::default(),
         }
 
-        // rende the screen
-        let fov_recompute = previous_player_position != (objects[PLAYER].pos()); // <1>
-        render_all(tcod, game, &objects, fov_recompute);
+        // render the screen
+        render_all(tcod, game, objects);
 
         tcod.root.flush();
 
-        // level up if needed
-        level_up(tcod, game, objects);
-
         // handle keys and exit game if needed
-        previous_player_position = objects[PLAYER].pos();
-        let player_action = handle_keys(tcod, game, objects);
+        let dungeon_level = game.dungeon_level;
+        let player_action = match tcod.travel.take() {
+            // travelling: a step every frame, until it stops or any key is pressed
+            Some(mut travel) => {
+                let command = match tcod.key.code {
+                    tcod::input::KeyCode::NoKey => travel.next_command(game, objects),
+                    _ => None,
+                };
+                match command {
+                    Some(command) => {
+                        tcod.travel = Some(travel);
+                        play_command(command, tcod, game, objects)
+                    }
+                    None => PlayerAction::DidntTakeTurn,
+                }
+            }
+            None => handle_keys(tcod, game, objects),
+        };
+        if let Some(Err(e)) = recorder.as_mut().map(|recorder| recorder.update(game)) {
+            game.messages.add(
+                format!("The game stopped being recorded: {}", e),
+                roguelike::colors::ORANGE,
+            );
+            recorder = None;
+        }
         if player_action == PlayerAction::Exit {
             save_game(game, objects).unwrap();
             break;
         }
-
-        // let monsters take their turn
-        if objects[PLAYER].alive && player_action != PlayerAction::DidntTakeTurn {
-            for id in 0..objects.len() {
-                if objects[id].ai.is_some() {
-                    ai_take_turn(id, tcod, game, objects);
-                }
-            }
+        if game.dungeon_level != dungeon_level {
+            initialise_map_console(tcod);
         }
     }
 }
 
-fn save_game(game: &mut Game, objects: &[Object]) -> Result<(), Box<dyn Error>> {
-    let save_data = serde_json::to_string(&(game, objects))?;
-    let mut file = File::create("savegame")?;
-    file.write_all(save_data.as_bytes())?;
-    Ok(())
-}
-
-fn load_game() -> Result<(Game, Vec<Object>), Box<dyn Error>> {
-    let mut json_save_state = String::new();
-    let mut file = File::open("savegame")?;
-    file.read_to_string(&mut json_save_state)?;
-    let result = serde_json::from_str::<(Game, Vec<Object>)>(&json_save_state)?;
-    Ok(result)
-}
-
-// Advance to the next level
-fn next_level(tcod: &mut Tcod, game: &mut Game, objects: &mut Vec<Object>) {
-    game.messages.add(
-        "You take a moment to rest, and recover your strength.",
-        GREEN,
-    );
-    let heal_hp = objects[PLAYER].max_hp(game) / 2;
-    objects[PLAYER].heal(heal_hp, game);
-
-    game.messages.add(
-        "After a rare moment of peace, you descend deeper into \n
-        the heart of the dungeon...",
-        RED,
-    );
-    game.dungeon_level += 1;
-    game.map = make_map(objects, game.dungeon_level);
-    initialise_fov(tcod, &game.map);
-}
-
-fn level_up(tcod: &mut Tcod, game: &mut Game, objects: &mut [Object]) {
-    let player = &mut objects[PLAYER];
-    let level_up_xp = LEVEL_UP_BASE + player.level * LEVEL_UP_FACTOR;
-
-    // see if the player's xp is enough to lvl up
-    if player.fighter.as_ref().map_or(0, |f| f.xp) >= level_up_xp {
-        // level up
-        player.level += 1;
-        game.messages.add(
-            format!(
-                "Your battle skills grow stronger! You reached level {}!",
-                player.level,
-            ),
-            YELLOW,
-        );
-        let fighter = player.fighter.as_mut().unwrap();
-        let mut choice  = None;
-        while choice.is_none() {
-            // keep asking until a choice is made
-            choice = menu(
-                "Level up! Choose your gift:\n",
-                &[
-                    format!("Constitution (+20 HP, from {})", fighter.base_max_hp),
-                    format!("Might (+1 ATK, from {})", fighter.base_power),
-                    format!("Bulwark (+1 DEF, from {})", fighter.base_defense),
-                ],
-                LEVEL_SCREEN_WIDTH,
-                &mut tcod.root,
-            );
+// show a recorded game, one command every `delay`; any key stops it
+fn play_replay(tcod: &mut Tcod, replay: Replay, delay: Duration) {
+    let mut replayer = Replayer::new(replay);
+    initialise_map_console(tcod);
+    loop {
+        tcod.con.clear();
+        render_all(tcod, &replayer.game, &replayer.objects);
+        tcod.root.flush();
+        if tcod.root.window_closed() {
+            return;
         }
-        fighter.xp -= level_up_xp;
-        match choice.unwrap() {
-            0 => {
-                fighter.base_max_hp += 20;
-                fighter.hp += 20;
-            }
-            1 => {
-                fighter.base_power += 1;
-            }
-            2 => {
-                fighter.base_defense += 1;
+        if let Some((_, Event::Key(_))) = input::check_for_event(input::KEY_PRESS) {
+            return;
+        }
+
+        thread::sleep(delay);
+        let dungeon_level = replayer.game.dungeon_level;
+        match replayer.step() {
+            Ok(true) => {}
+            Ok(false) => break,
+            Err(e) => {
+                eprintln!("{}", e);
+                process::exit(1);
             }
-            _ => unreachable!(),
+        }
+        if replayer.game.dungeon_level != dungeon_level {
+            initialise_map_console(tcod);
         }
     }
+    msgbox("\nEnd of the replay.\n", 24, &mut tcod.root);
 }
 
-fn main_menu(tcod: &mut Tcod) {
+// `seed` comes from the command line and is used for "New Game"; without one
+// every new game gets a random seed
+fn main_menu(tcod: &mut Tcod, seed: Option<u64>) {
     let img = tcod::image::Image::from_file("menu_background.png")
-        .ok()
         .expect("Background image not found");
 
     while !tcod.root.window_closed() {
@@ -1965,29 +742,38 @@ fn main_menu(tcod: &mut Tcod) {
         );
 
         // show the options and wait for the player's choice
-        let choices = &["New Game", "Load Game", "Quit"];
+        let choices = &["New Game", "New Game with Seed", "Load Game", "Quit"];
         let choice = menu("", choices, 24, &mut tcod.root);
 
         match choice {
             Some(0) => {
                 // new game
-                let (mut game, mut objects) = new_game(tcod);
+                let (mut game, mut objects) = new_game(seed.unwrap_or_else(random_seed));
+                initialise_map_console(tcod);
                 play_game(tcod, &mut game, &mut objects);
             }
             Some(1) => {
+                // new game from a seed typed in by the player
+                if let Some(seed) = seed_prompt(24, &mut tcod.root) {
+                    let (mut game, mut objects) = new_game(seed);
+                    initialise_map_console(tcod);
+                    play_game(tcod, &mut game, &mut objects);
+                }
+            }
+            Some(2) => {
                 // load game
                 match load_game() {
                     Ok((mut game, mut objects)) => {
-                        initialise_fov(tcod, &game.map);
+                        initialise_map_console(tcod);
                         play_game(tcod, &mut game, &mut objects);
                     }
-                    Err(_e) => {
-                        msgbox("\nNo saved game to load.\n", 24, &mut tcod.root);
+                    Err(e) => {
+                        msgbox(&format!("\n{}\n", e), 24, &mut tcod.root);
                         continue;
                     }
                 }
             }
-            Some(2) => {
+            Some(3) => {
                 //quit
                 break;
             }
@@ -1996,7 +782,106 @@ fn main_menu(tcod: &mut Tcod) {
     }
 }
 
+// command line options
+struct Options {
+    // `--seed <n>`: the seed of new games
+    seed: Option<u64>,
+    // `--replay <file>`: play back a recorded game instead of showing the menu
+    replay: Option<String>,
+    // `--headless`: with `--replay`, run it without a window and print the outcome
+    headless: bool,
+    // `--speed <ms>`: time between commands when showing a replay
+    replay_delay: Duration,
+    // `--bot <games>`: let the bot play that many games without a window and
+    // print statistics about them
+    bot_games: Option<u32>,
+    // `--bot-heal <percent>`: HP below which the bot drinks a potion
+    bot: BotConfig,
+}
+
+impl Options {
+    fn from_args() -> Self {
+        let mut options = Options {
+            seed: None,
+            replay: None,
+            headless: false,
+            replay_delay: Duration::from_millis(100),
+            bot_games: None,
+            bot: BotConfig::default(),
+        };
+        let mut args = env::args().skip(1);
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--seed" => options.seed = Some(option_value(&arg, args.next())),
+                "--replay" => options.replay = Some(option_value(&arg, args.next())),
+                "--headless" => options.headless = true,
+                "--speed" => {
+                    options.replay_delay = Duration::from_millis(option_value(&arg, args.next()))
+                }
+                "--bot" => options.bot_games = Some(option_value(&arg, args.next())),
+                "--bot-heal" => {
+                    options.bot.heal_below = option_value::<f32>(&arg, args.next()) / 100.0
+                }
+                _ => exit_with_usage(&format!("unknown option {}", arg)),
+            }
+        }
+        if options.headless && options.replay.is_none() {
+            exit_with_usage("--headless needs --replay <file>");
+        }
+        options
+    }
+}
+
+fn option_value<T: std::str::FromStr>(option: &str, value: Option<String>) -> T {
+    match value.map(|value| value.parse()) {
+        Some(Ok(value)) => value,
+        _ => exit_with_usage(&format!("{} needs a valid value", option)),
+    }
+}
+
+fn exit_with_usage(problem: &str) -> ! {
+    eprintln!("{}", problem);
+    eprintln!(
+        "usage: roguelike [--seed <n>] [--replay <file> [--headless | --speed <ms>]]\n       \
+         roguelike --bot <games> [--seed <first seed>] [--bot-heal <percent>]"
+    );
+    process::exit(1);
+}
+
 fn main() {
+    let options = Options::from_args();
+    // read the vaults, the bestiary and the items up front, so a mistake in
+    // any of the files shows up straight away
+    if let Err(e) = load_raws().and_then(|_| load_vaults()) {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+    let replay = options.replay.as_ref().map(|path| {
+        load_replay(path).unwrap_or_else(|e| {
+            eprintln!("Could not read the replay {}: {}", path, e);
+            process::exit(1);
+        })
+    });
+
+    if let Some(games) = options.bot_games {
+        // balance testing: no window, just numbers
+        let stats = run_bot_games(options.seed.unwrap_or(0), games, options.bot);
+        print!("{}", stats);
+        return;
+    }
+
+    if options.headless {
+        // no window needed: replay the game and report how it went
+        match run_headless(replay.unwrap()) {
+            Ok((game, objects)) => println!("{}", summary(&game, &objects)),
+            Err(e) => {
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+        }
+        return;
+    }
+
     tcod::system::set_fps(LIMIT_FPS);
 
     let root = Root::initializer()
@@ -2010,12 +895,15 @@ fn main() {
         root,
         con: Offscreen::new(MAP_WIDTH, MAP_HEIGHT),
         panel: Offscreen::new(SCREEN_WIDTH, PANEL_HEIGHT),
-        fov: FovMap::new(MAP_WIDTH, MAP_HEIGHT),
         key: Default::default(),
         mouse: Default::default(),
+        travel: None,
     };
 
     env::set_var("RUST_BACKTRACE", "1");
 
-    main_menu(&mut tcod);
+    match replay {
+        Some(replay) => play_replay(&mut tcod, replay, options.replay_delay),
+        None => main_menu(&mut tcod, options.seed),
+    }
 }
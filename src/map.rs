@@ -0,0 +1,972 @@
+use std::cmp;
+use std::collections::VecDeque;
+
+use rand::distributions::{IndependentSample, Weighted, WeightedChoice};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::ai::Ai;
+use crate::colors::*;
+use crate::object::{is_blocked, DeathCallback, Fighter, Object};
+use crate::raws::{
+    item_kind, items, monster_kind, monsters, AiKind, ITEMS_FILE, KEY, MONSTERS_FILE,
+};
+use crate::rng::GameRng;
+use crate::vault::{vaults, Glyph, Vault};
+use crate::PLAYER;
+
+// size of the map
+pub const MAP_WIDTH: i32 = 80;
+pub const MAP_HEIGHT: i32 = 43;
+
+// room parameters
+const ROOM_MAX_SIZE: i32 = 10;
+const ROOM_MIN_SIZE: i32 = 6;
+const MAX_ROOMS: i32 = 30;
+// BSP areas are never split into parts smaller than this
+const BSP_MIN_AREA_SIZE: i32 = ROOM_MIN_SIZE + 2;
+
+// cave parameters
+const CAVE_WALL_CHANCE: f32 = 0.45;
+const CAVE_SMOOTHING_STEPS: i32 = 5;
+// a cave smaller than this is thrown away and dug again
+const CAVE_MIN_FLOOR: usize = (MAP_WIDTH * MAP_HEIGHT / 3) as usize;
+// caves are split into squares of this size to spread monsters and items
+const CAVE_REGION_SIZE: i32 = ROOM_MAX_SIZE;
+// percent chance of a door where a tunnel goes into a room
+const DOOR_CHANCE: u32 = 50;
+// how many random spots to try before giving up on a vault
+const VAULT_PLACEMENT_TRIES: i32 = 50;
+// percent chance of a monster being up and about instead of asleep
+const WANDER_CHANCE: u32 = 25;
+
+pub type Map = Vec<Vec<Tile>>;
+
+// A level the player isn't on, kept just as they left it
+#[derive(Serialize, Deserialize)]
+pub struct Level {
+    pub map: Map,
+    // everything on the level but the player
+    pub objects: Vec<Object>,
+}
+
+// A tile of the map and its properties
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct Tile {
+    pub blocked: bool,
+    pub explored: bool,
+    pub block_sight: bool,
+    pub door: Option<Door>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Door {
+    Open,
+    Closed,
+    // closed, and only a key will open it
+    Locked,
+}
+
+impl Tile {
+    pub fn empty() -> Self {
+        Tile {
+            blocked: false,
+            explored: false,
+            block_sight: false,
+            door: None,
+        }
+    }
+
+    pub fn wall() -> Self {
+        Tile {
+            blocked: true,
+            explored: false,
+            block_sight: true,
+            door: None,
+        }
+    }
+
+    pub fn door(door: Door) -> Self {
+        let mut tile = Tile::empty();
+        tile.set_door(door);
+        tile
+    }
+
+    // open, close or lock a door. a shut door is as solid as a wall
+    pub fn set_door(&mut self, door: Door) {
+        self.door = Some(door);
+        self.blocked = door != Door::Open;
+        self.block_sight = door != Door::Open;
+    }
+}
+
+// A rectangle on the map, used to characterise a room.
+#[derive(Clone, Copy, Debug)]
+pub struct Rect {
+    pub x1: i32,
+    pub y1: i32,
+    pub x2: i32,
+    pub y2: i32,
+}
+
+impl Rect {
+    pub fn new(x: i32, y: i32, w: i32, h: i32) -> Self {
+        Rect {
+            x1: x,
+            y1: y,
+            x2: x + w,
+            y2: y + h,
+        }
+    }
+
+    pub fn center(&self) -> (i32, i32) {
+        let center_x = (self.x1 + self.x2) / 2;
+        let center_y = (self.y1 + self.y2) / 2;
+        (center_x, center_y)
+    }
+
+    pub fn intersects_with(&self, other: &Rect) -> bool {
+        // returns true if this rectangle intersects with another one
+        (self.x1 <= other.x2)
+            && (self.x2 >= other.x1)
+            && (self.y1 <= other.y2)
+            && (self.y2 >= other.y1)
+    }
+}
+
+pub fn create_room(room: Rect, map: &mut Map) {
+    // go through the tiles in the rectangle and make them passable
+    for x in (room.x1 + 1)..room.x2 {
+        for y in (room.y1 + 1)..room.y2 {
+            map[x as usize][y as usize] = Tile::empty();
+        }
+    }
+}
+
+pub fn create_h_tunnel(x1: i32, x2: i32, y: i32, map: &mut Map) {
+    // horizontal tunnel. `min()` and `max()` are used in case `x1 > x2`
+    for x in cmp::min(x1, x2)..(cmp::max(x1, x2) + 1) {
+        map[x as usize][y as usize] = Tile::empty();
+    }
+}
+
+pub fn create_v_tunnel(y1: i32, y2: i32, x: i32, map: &mut Map) {
+    // vertical tunnel
+    for y in cmp::min(y1, y2)..(cmp::max(y1, y2) + 1) {
+        map[x as usize][y as usize] = Tile::empty();
+    }
+}
+
+// The ways a dungeon level can be laid out.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Layout {
+    // random rectangles, each joined to the previous one
+    RoomsAndTunnels,
+    // binary space partition: nested areas with branching corridors
+    Bsp,
+    // one big natural cave, grown by cellular automata
+    Caves,
+}
+
+// pick the layout of a level; deeper levels get more varied layouts
+fn choose_layout(level: u32, rng: &mut GameRng) -> Layout {
+    let layout_chances = &mut [
+        Weighted {
+            weight: 50,
+            item: Layout::RoomsAndTunnels,
+        },
+        Weighted {
+            weight: from_dungeon_level(
+                &[
+                    Transition { level: 2, value: 30 },
+                    Transition { level: 4, value: 50 },
+                ],
+                level,
+            ),
+            item: Layout::Bsp,
+        },
+        Weighted {
+            weight: from_dungeon_level(
+                &[
+                    Transition { level: 3, value: 20 },
+                    Transition { level: 6, value: 40 },
+                ],
+                level,
+            ),
+            item: Layout::Caves,
+        },
+    ];
+    WeightedChoice::new(layout_chances).ind_sample(rng)
+}
+
+pub fn make_map(objects: &mut Vec<Object>, level: u32, rng: &mut GameRng) -> Map {
+    // fill map with "blocked" tiles
+    let mut map = vec![vec![Tile::wall(); MAP_HEIGHT as usize]; MAP_WIDTH as usize];
+
+    // Player is the first element, remove everything else
+    // NOTE: works only when the player is the first object!
+    assert_eq!(&objects[PLAYER] as *const _, &objects[0] as *const _);
+    objects.truncate(1);
+
+    // dig out the rooms and whatever joins them
+    let layout = choose_layout(level, rng);
+    let rooms = loop {
+        let rooms = match layout {
+            Layout::RoomsAndTunnels => dig_rooms_and_tunnels(&mut map, rng),
+            Layout::Bsp => dig_bsp(&mut map, rng),
+            Layout::Caves => dig_caves(&mut map, rng),
+        };
+        if !rooms.is_empty() {
+            break rooms;
+        }
+        // nowhere to put the player: start again from solid rock
+        map = vec![vec![Tile::wall(); MAP_HEIGHT as usize]; MAP_WIDTH as usize];
+    };
+
+    if layout != Layout::Caves {
+        place_doors(&rooms, &mut map, rng);
+    }
+
+    // maybe add some hand-made rooms, and remember where they went
+    let mut vault_areas = vec![];
+    for vault in vaults() {
+        if rng.gen_range(0, 100) < from_dungeon_level(&vault.chance, level) {
+            if let Some(area) = place_vault(vault, &mut map, &vault_areas, objects, rng) {
+                vault_areas.push(area);
+            }
+        }
+    }
+
+    // the player starts in the first room
+    let (first_room_x, first_room_y) = nearest_floor(&map, rooms[0].center(), &vault_areas);
+    objects[PLAYER].set_pos(first_room_x, first_room_y);
+
+    // the way back up, from every level but the first
+    if level > 1 {
+        let mut up_stairs = Object::new(first_room_x, first_room_y, '>', "up stairs", WHITE, false);
+        up_stairs.always_visible = true;
+        objects.push(up_stairs);
+    }
+
+    // add some content to every room, such as monsters
+    for &room in &rooms {
+        place_objects(room, &map, objects, level, rng);
+    }
+
+    // a vault with a locked door needs a key somewhere outside of the vaults
+    for area in &vault_areas {
+        let locked = (area.x1..=area.x2).any(|x| {
+            (area.y1..=area.y2).any(|y| map[x as usize][y as usize].door == Some(Door::Locked))
+        });
+        if locked {
+            let room = rooms[rng.gen_range(0, rooms.len())];
+            let (key_x, key_y) = nearest_floor(&map, room.center(), &vault_areas);
+            objects.push(make_item(KEY, key_x, key_y));
+        }
+    }
+
+    // create stairs at the center of the last room
+    let (last_room_x, last_room_y) =
+        nearest_floor(&map, rooms[rooms.len() - 1].center(), &vault_areas);
+    let mut stairs = Object::new(last_room_x, last_room_y, '<', "stairs", WHITE, false);
+    stairs.always_visible = true;
+    objects.push(stairs);
+
+    connect_unreachable(&mut map, objects, &vault_areas);
+
+    map
+}
+
+// put doors in some of the gaps the tunnels left in the room walls
+fn place_doors(rooms: &[Rect], map: &mut Map, rng: &mut GameRng) {
+    for room in rooms {
+        // the walls around the room, without the corners
+        let top_and_bottom = (room.x1 + 1..room.x2).flat_map(|x| [(x, room.y1), (x, room.y2)]);
+        let sides = (room.y1 + 1..room.y2).flat_map(|y| [(room.x1, y), (room.x2, y)]);
+        for (x, y) in top_and_bottom.chain(sides) {
+            // a doorway is a gap with wall on either side of it
+            let (before, after) = if y == room.y1 || y == room.y2 {
+                ((x - 1, y), (x + 1, y))
+            } else {
+                ((x, y - 1), (x, y + 1))
+            };
+            let doorway = !map[x as usize][y as usize].blocked
+                && map[before.0 as usize][before.1 as usize].blocked
+                && map[after.0 as usize][after.1 as usize].blocked;
+            // one door is enough where two rooms nearly touch
+            let next_to_door = || {
+                (x - 1..=x + 1)
+                    .any(|x| (y - 1..=y + 1).any(|y| map[x as usize][y as usize].door.is_some()))
+            };
+            if doorway && !next_to_door() && rng.gen_range(0, 100) < DOOR_CHANCE {
+                map[x as usize][y as usize] = Tile::door(Door::Closed);
+            }
+        }
+    }
+}
+
+// stamp a vault into solid rock, so it can't cut into the rooms already dug,
+// and tunnel out of it to the nearest floor. returns the vault with the rock
+// around it, or None if there was no space for it
+fn place_vault(
+    vault: &Vault,
+    map: &mut Map,
+    vault_areas: &[Rect],
+    objects: &mut Vec<Object>,
+    rng: &mut GameRng,
+) -> Option<Rect> {
+    let (w, h) = (vault.width(), vault.height());
+    for _ in 0..VAULT_PLACEMENT_TRIES {
+        // keep a tile of rock between it and the edge of the map, for digging
+        // a way out of its doors
+        let x = rng.gen_range(2, MAP_WIDTH - w - 1);
+        let y = rng.gen_range(2, MAP_HEIGHT - h - 1);
+        // the vault plus a wall of rock around it
+        let area = Rect::new(x - 1, y - 1, w + 1, h + 1);
+        let solid = (area.x1..=area.x2)
+            .all(|x| (area.y1..=area.y2).all(|y| map[x as usize][y as usize].blocked));
+        if !solid {
+            continue;
+        }
+
+        for (dy, row) in vault.rows.iter().enumerate() {
+            for (dx, glyph) in row.iter().enumerate() {
+                let (tile_x, tile_y) = (x + dx as i32, y + dy as i32);
+                let tile = &mut map[tile_x as usize][tile_y as usize];
+                match glyph {
+                    Glyph::Keep => {}
+                    Glyph::Wall => *tile = Tile::wall(),
+                    Glyph::Floor => *tile = Tile::empty(),
+                    Glyph::Door(door) => *tile = Tile::door(*door),
+                    Glyph::Monster(name) => {
+                        *tile = Tile::empty();
+                        objects.push(make_monster(name, tile_x, tile_y));
+                    }
+                    Glyph::Item(item) => {
+                        *tile = Tile::empty();
+                        objects.push(make_item(item, tile_x, tile_y));
+                    }
+                }
+            }
+        }
+
+        // the way in: out of each of its doors, or if it has none from its
+        // middle through its walls, to the nearest floor outside. never
+        // through another vault, which could be a way around its locks
+        let tiles = (x..x + w).flat_map(|x| (y..y + h).map(move |y| (x, y)));
+        let doors: Vec<(i32, i32)> = tiles
+            .clone()
+            .filter(|&(x, y)| map[x as usize][y as usize].door.is_some())
+            .collect();
+        let outside = |map: &Map, (x, y): (i32, i32)| {
+            let tile = &map[x as usize][y as usize];
+            (!tile.blocked || tile.door.is_some()) && !in_vault(&[area], (x, y))
+        };
+        for &door in &doors {
+            dig_until(
+                door,
+                outside,
+                |tile| in_vault(vault_areas, tile) || in_vault(&[area], tile),
+                map,
+            );
+        }
+        if doors.is_empty() {
+            let (center_x, center_y) = area.center();
+            let middle = tiles
+                .filter(|&(x, y)| !map[x as usize][y as usize].blocked)
+                .min_by_key(|&(x, y)| (x - center_x).pow(2) + (y - center_y).pow(2));
+            if let Some(middle) = middle {
+                dig_until(middle, outside, |tile| in_vault(vault_areas, tile), map);
+            }
+        }
+        return Some(area);
+    }
+    None
+}
+
+// whether the tile is part of one of the vaults, not counting the rock
+// around them
+fn in_vault(vault_areas: &[Rect], (x, y): (i32, i32)) -> bool {
+    vault_areas
+        .iter()
+        .any(|area| area.x1 < x && x < area.x2 && area.y1 < y && y < area.y2)
+}
+
+// dig the shortest way from `from` to the first tile that's `found`, never
+// through a tile to `avoid` or the edge of the map. the two ends are left
+// as they are
+fn dig_until(
+    from: (i32, i32),
+    found: impl Fn(&Map, (i32, i32)) -> bool,
+    avoid: impl Fn((i32, i32)) -> bool,
+    map: &mut Map,
+) {
+    let mut came_from = vec![vec![None; MAP_HEIGHT as usize]; MAP_WIDTH as usize];
+    let mut queue = VecDeque::from(vec![from]);
+    while let Some((x, y)) = queue.pop_front() {
+        for (dx, dy) in [(0, -1), (0, 1), (-1, 0), (1, 0)] {
+            let next = (x + dx, y + dy);
+            if next.0 < 1 || next.1 < 1 || next.0 >= MAP_WIDTH - 1 || next.1 >= MAP_HEIGHT - 1 {
+                continue;
+            }
+            if avoid(next) || next == from || came_from[next.0 as usize][next.1 as usize].is_some()
+            {
+                continue;
+            }
+            came_from[next.0 as usize][next.1 as usize] = Some((x, y));
+            if found(map, next) {
+                // dig back to where it started, leaving any doors on the way
+                let mut tile = (x, y);
+                while tile != from {
+                    if map[tile.0 as usize][tile.1 as usize].door.is_none() {
+                        map[tile.0 as usize][tile.1 as usize] = Tile::empty();
+                    }
+                    tile = came_from[tile.0 as usize][tile.1 as usize].unwrap();
+                }
+                return;
+            }
+            queue.push_back(next);
+        }
+    }
+}
+
+// make sure the stairs and every item can be walked to from where the player
+// starts, digging a tunnel to anything that can't. what's in a vault is got
+// to through its doors, and the tunnels go around the vaults, so a locked one
+// stays locked
+fn connect_unreachable(map: &mut Map, objects: &[Object], vault_areas: &[Rect]) {
+    let start = objects[PLAYER].pos();
+    let mut reachable = reachable_tiles(map, start);
+    for object in objects {
+        if object.item.is_none() && object.name != "stairs" {
+            continue;
+        }
+        let (x, y) = object.pos();
+        if in_vault(vault_areas, (x, y)) || reachable[x as usize][y as usize] {
+            continue;
+        }
+        dig_until(
+            (x, y),
+            |_, (x, y)| reachable[x as usize][y as usize],
+            |tile| in_vault(vault_areas, tile),
+            map,
+        );
+        reachable = reachable_tiles(map, start);
+    }
+}
+
+// connect two points with an L-shaped tunnel
+pub fn create_tunnel(from: (i32, i32), to: (i32, i32), map: &mut Map, rng: &mut GameRng) {
+    let ((prev_x, prev_y), (new_x, new_y)) = (from, to);
+    // toss a coin (random bool value -- either true or false)
+    if rng.gen() {
+        // first move horizontally, then vertically
+        create_h_tunnel(prev_x, new_x, prev_y, map);
+        create_v_tunnel(prev_y, new_y, new_x, map);
+    } else {
+        // first move vertically, then horizontally
+        create_v_tunnel(prev_y, new_y, prev_x, map);
+        create_h_tunnel(prev_x, new_x, new_y, map);
+    }
+}
+
+// place up to MAX_ROOMS random rooms, joining each to the previous one
+fn dig_rooms_and_tunnels(map: &mut Map, rng: &mut GameRng) -> Vec<Rect> {
+    let mut rooms: Vec<Rect> = vec![];
+
+    for _ in 0..MAX_ROOMS {
+        // random width and height
+        let w = rng.gen_range(ROOM_MIN_SIZE, ROOM_MAX_SIZE + 1);
+        let h = rng.gen_range(ROOM_MIN_SIZE, ROOM_MAX_SIZE + 1);
+        // random position without going out of the boundaries of the map
+        let x = rng.gen_range(0, MAP_WIDTH - w);
+        let y = rng.gen_range(0, MAP_HEIGHT - h);
+
+        let new_room = Rect::new(x, y, w, h);
+
+        // run through the other rooms and see if they intersect with this one
+        let failed = rooms
+            .iter()
+            .any(|other_room| new_room.intersects_with(other_room));
+
+        if !failed {
+            // this means there are no intersections, so this room is valid
+
+            // "paint" it to the map's tiles
+            create_room(new_room, map);
+
+            // all rooms after the first:
+            // connect it to the previous room with a tunnel
+            if let Some(prev_room) = rooms.last() {
+                create_tunnel(prev_room.center(), new_room.center(), map, rng);
+            }
+
+            // finally, append the new room to the list
+            rooms.push(new_room);
+        }
+    }
+    rooms
+}
+
+// split the map in two, again and again, put a room in each of the smallest
+// parts, then join the two halves of every split with a corridor
+fn dig_bsp(map: &mut Map, rng: &mut GameRng) -> Vec<Rect> {
+    let mut rooms = vec![];
+    let whole_map = Rect::new(0, 0, MAP_WIDTH - 1, MAP_HEIGHT - 1);
+    dig_bsp_area(whole_map, map, rng, &mut rooms);
+    rooms
+}
+
+// dig rooms into one area of the BSP tree; returns a room of the area for
+// the parent split to connect to
+fn dig_bsp_area(area: Rect, map: &mut Map, rng: &mut GameRng, rooms: &mut Vec<Rect>) -> Rect {
+    let width = area.x2 - area.x1;
+    let height = area.y2 - area.y1;
+    let can_split_x = width >= 2 * BSP_MIN_AREA_SIZE;
+    let can_split_y = height >= 2 * BSP_MIN_AREA_SIZE;
+    // areas that could hold a couple of rooms sometimes get only one, so
+    // that room sizes vary
+    let small = width <= 2 * ROOM_MAX_SIZE && height <= 2 * ROOM_MAX_SIZE;
+
+    if !(can_split_x || can_split_y) || (small && rng.gen_weighted_bool(3)) {
+        // a leaf: put one room somewhere inside it
+        let w = rng.gen_range(ROOM_MIN_SIZE, cmp::min(ROOM_MAX_SIZE, width) + 1);
+        let h = rng.gen_range(ROOM_MIN_SIZE, cmp::min(ROOM_MAX_SIZE, height) + 1);
+        let x = rng.gen_range(area.x1, area.x2 - w + 1);
+        let y = rng.gen_range(area.y1, area.y2 - h + 1);
+        let room = Rect::new(x, y, w, h);
+        create_room(room, map);
+        rooms.push(room);
+        return room;
+    }
+
+    // split across the longer side, unless only the other one is possible
+    let split_x = can_split_x && (!can_split_y || width > height);
+    let (first, second) = if split_x {
+        let x = rng.gen_range(area.x1 + BSP_MIN_AREA_SIZE, area.x2 - BSP_MIN_AREA_SIZE + 1);
+        (
+            Rect::new(area.x1, area.y1, x - area.x1, height),
+            Rect::new(x, area.y1, area.x2 - x, height),
+        )
+    } else {
+        let y = rng.gen_range(area.y1 + BSP_MIN_AREA_SIZE, area.y2 - BSP_MIN_AREA_SIZE + 1);
+        (
+            Rect::new(area.x1, area.y1, width, y - area.y1),
+            Rect::new(area.x1, y, width, area.y2 - y),
+        )
+    };
+    let first_room = dig_bsp_area(first, map, rng, rooms);
+    let second_room = dig_bsp_area(second, map, rng, rooms);
+    create_tunnel(first_room.center(), second_room.center(), map, rng);
+
+    // hand a random room up, so corridors branch off all over the tree
+    if rng.gen() {
+        first_room
+    } else {
+        second_room
+    }
+}
+
+// grow a cave out of random noise, keep only its biggest connected part,
+// then split it into square regions for placing monsters and items
+fn dig_caves(map: &mut Map, rng: &mut GameRng) -> Vec<Rect> {
+    loop {
+        // random noise, with a solid border
+        for x in 1..MAP_WIDTH - 1 {
+            for y in 1..MAP_HEIGHT - 1 {
+                map[x as usize][y as usize] = if rng.next_f32() < CAVE_WALL_CHANCE {
+                    Tile::wall()
+                } else {
+                    Tile::empty()
+                };
+            }
+        }
+
+        // smooth it: a tile becomes a wall when most of its neighbours are
+        for _ in 0..CAVE_SMOOTHING_STEPS {
+            let previous = map.clone();
+            for x in 1..MAP_WIDTH - 1 {
+                for y in 1..MAP_HEIGHT - 1 {
+                    let walls = (x - 1..=x + 1)
+                        .flat_map(|nx| (y - 1..=y + 1).map(move |ny| (nx, ny)))
+                        .filter(|&(nx, ny)| previous[nx as usize][ny as usize].blocked)
+                        .count();
+                    map[x as usize][y as usize] = if walls >= 5 {
+                        Tile::wall()
+                    } else {
+                        Tile::empty()
+                    };
+                }
+            }
+        }
+
+        // find the biggest cave and fill in all the others
+        let mut biggest: Option<Vec<Vec<bool>>> = None;
+        let mut biggest_size = 0;
+        let mut seen = vec![vec![false; MAP_HEIGHT as usize]; MAP_WIDTH as usize];
+        for x in 0..MAP_WIDTH {
+            for y in 0..MAP_HEIGHT {
+                if map[x as usize][y as usize].blocked || seen[x as usize][y as usize] {
+                    continue;
+                }
+                let cave = reachable_tiles(map, (x, y));
+                let size = cave.iter().flatten().filter(|&&tile| tile).count();
+                for (seen_column, cave_column) in seen.iter_mut().zip(&cave) {
+                    for (seen_tile, &cave_tile) in seen_column.iter_mut().zip(cave_column) {
+                        *seen_tile |= cave_tile;
+                    }
+                }
+                if size > biggest_size {
+                    biggest = Some(cave);
+                    biggest_size = size;
+                }
+            }
+        }
+        if biggest_size < CAVE_MIN_FLOOR {
+            continue;
+        }
+        let biggest = biggest.unwrap();
+        for x in 0..MAP_WIDTH as usize {
+            for y in 0..MAP_HEIGHT as usize {
+                if !biggest[x][y] {
+                    map[x][y] = Tile::wall();
+                }
+            }
+        }
+
+        // squares of the map with a fair amount of cave in them, from the top
+        // left to the bottom right, so the player and stairs end up far apart
+        let mut regions = vec![];
+        for y in (0..MAP_HEIGHT - 1).step_by(CAVE_REGION_SIZE as usize) {
+            for x in (0..MAP_WIDTH - 1).step_by(CAVE_REGION_SIZE as usize) {
+                let w = cmp::min(CAVE_REGION_SIZE, MAP_WIDTH - 1 - x);
+                let h = cmp::min(CAVE_REGION_SIZE, MAP_HEIGHT - 1 - y);
+                let region = Rect::new(x, y, w, h);
+                let floor = (region.x1 + 1..region.x2)
+                    .flat_map(|x| (region.y1 + 1..region.y2).map(move |y| (x, y)))
+                    .filter(|&(x, y)| !map[x as usize][y as usize].blocked)
+                    .count() as i32;
+                if floor * 4 >= (w - 1) * (h - 1) {
+                    regions.push(region);
+                }
+            }
+        }
+        return regions;
+    }
+}
+
+// every tile that can be walked to from `start`, moving like the player does
+pub fn reachable_tiles(map: &Map, start: (i32, i32)) -> Vec<Vec<bool>> {
+    let mut reached = vec![vec![false; MAP_HEIGHT as usize]; MAP_WIDTH as usize];
+    let mut stack = vec![start];
+    reached[start.0 as usize][start.1 as usize] = true;
+    while let Some((x, y)) = stack.pop() {
+        for dx in -1..=1 {
+            for dy in -1..=1 {
+                let (nx, ny) = (x + dx, y + dy);
+                if nx < 0 || ny < 0 || nx >= MAP_WIDTH || ny >= MAP_HEIGHT {
+                    continue;
+                }
+                let tile = &map[nx as usize][ny as usize];
+                // doors can be opened, but a locked one only with its key,
+                // which has to be got to some other way
+                let passable =
+                    !tile.blocked || matches!(tile.door, Some(Door::Closed | Door::Open));
+                if !reached[nx as usize][ny as usize] && passable {
+                    reached[nx as usize][ny as usize] = true;
+                    stack.push((nx, ny));
+                }
+            }
+        }
+    }
+    reached
+}
+
+// the floor tile closest to the given position (which is often the
+// position itself), outside of the given vaults
+fn nearest_floor(map: &Map, (x, y): (i32, i32), vault_areas: &[Rect]) -> (i32, i32) {
+    let mut closest = (x, y);
+    let mut closest_distance = i32::MAX;
+    for tile_x in 0..MAP_WIDTH {
+        for tile_y in 0..MAP_HEIGHT {
+            let distance = (tile_x - x).pow(2) + (tile_y - y).pow(2);
+            if !map[tile_x as usize][tile_y as usize].blocked
+                && !in_vault(vault_areas, (tile_x, tile_y))
+                && distance < closest_distance
+            {
+                closest = (tile_x, tile_y);
+                closest_distance = distance;
+            }
+        }
+    }
+    closest
+}
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Transition {
+    pub level: u32,
+    pub value: u32,
+}
+
+// returns a value that depends on level. the table specifies what value
+// occurs after each level, default is 0
+pub fn from_dungeon_level(table: &[Transition], level: u32) -> u32 {
+    table
+        .iter()
+        .rev()
+        .find(|transition| level >= transition.level)
+        .map_or(0, |transition| transition.value)
+}
+
+pub fn place_objects(
+    room: Rect,
+    map: &Map,
+    objects: &mut Vec<Object>,
+    level: u32,
+    rng: &mut GameRng,
+) {
+    // max no of monsters per room
+    let max_monsters = from_dungeon_level(
+        &[
+            Transition { level: 1, value: 2 },
+            Transition { level: 4, value: 3 },
+            Transition { level: 6, value: 5 },
+        ],
+        level,
+    );
+
+    // choose random number of monsters
+    let num_monsters = rng.gen_range(0, max_monsters + 1);
+
+    // monster random table, from the bestiary
+    let monster_chances = &mut monsters()
+        .iter()
+        .map(|kind| Weighted {
+            weight: from_dungeon_level(&kind.spawn, level),
+            item: kind.name.as_str(),
+        })
+        .collect::<Vec<_>>();
+    let monster_choice = WeightedChoice::new(monster_chances);
+
+    for _ in 0..num_monsters {
+        // choose random spot for this monster
+        let x = rng.gen_range(room.x1 + 1, room.x2);
+        let y = rng.gen_range(room.y1 + 1, room.y2);
+
+        // only place it if the tile is not blocked
+        if !is_blocked(x, y, map, objects) {
+            let mut monster = make_monster(monster_choice.ind_sample(rng), x, y);
+            // a few of the sleepers are up and about
+            if rng.gen_range(0, 100) < WANDER_CHANCE {
+                monster.ai = Some(Ai::Wandering { destination: None });
+            }
+            objects.push(monster);
+        }
+    }
+
+    // maximum no of items per room
+    let max_items = from_dungeon_level(
+        &[
+            Transition { level: 1, value: 1 },
+            Transition { level: 4, value: 2},
+        ],
+        level,
+    );
+
+    // item random table, from the items file
+    let item_chances = &mut items()
+        .iter()
+        .map(|kind| Weighted {
+            weight: from_dungeon_level(&kind.spawn, level),
+            item: kind.name.as_str(),
+        })
+        .collect::<Vec<_>>();
+    let item_choice = WeightedChoice::new(item_chances);
+
+    // choose random number of items
+    let num_items = rng.gen_range(0, max_items + 1);
+
+    for _ in 0..num_items {
+        // choose random spot for this item
+        let x = rng.gen_range(room.x1 + 1, room.x2);
+        let y = rng.gen_range(room.y1 + 1, room.y2);
+
+        //only place if the tile is not blocked
+        if !is_blocked(x, y, map, objects) {
+            let item = make_item(item_choice.ind_sample(rng), x, y);
+            objects.push(item);
+        }
+    }
+}
+
+// create a monster of the given kind, as the bestiary describes it
+pub fn make_monster(name: &str, x: i32, y: i32) -> Object {
+    let kind = monster_kind(name)
+        .unwrap_or_else(|| panic!("There's no monster called `{}` in {}", name, MONSTERS_FILE));
+    let mut monster = Object::new(x, y, kind.glyph, &kind.name, kind.color, true);
+    monster.fighter = Some(Fighter {
+        base_max_hp: kind.hp,
+        hp: kind.hp,
+        base_defense: kind.defense,
+        base_power: kind.power,
+        xp: kind.xp,
+        on_death: DeathCallback::Monster,
+        accuracy: kind.accuracy,
+        evasion: kind.evasion,
+        damage: kind.damage,
+        resistances: kind.resistances,
+    });
+    monster.ai = Some(match kind.ai {
+        AiKind::Sleeps => Ai::Asleep,
+        AiKind::Wanders => Ai::Wandering { destination: None },
+    });
+    monster.perception = kind.perception;
+    monster.speed = kind.speed;
+    monster.ranged = kind.ranged.clone();
+    monster.alive = true;
+    monster
+}
+
+// create an item of the given kind, lying on the floor
+pub fn make_item(name: &str, x: i32, y: i32) -> Object {
+    let kind = item_kind(name)
+        .unwrap_or_else(|| panic!("There's no item called `{}` in {}", name, ITEMS_FILE));
+    let mut object = Object::new(x, y, kind.glyph, &kind.name, kind.color, false);
+    object.item = Some(kind.effect);
+    object.equipment = kind.equipment;
+    object.count = kind.count;
+    object.always_visible = true;
+    object
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::item::Item;
+
+    // a map of the given depth, and everything on it, with the player first
+    fn generate(seed: u64, level: u32) -> (Map, Vec<Object>) {
+        let mut objects = vec![Object::new(0, 0, '@', "Player", WHITE, true)];
+        let map = make_map(&mut objects, level, &mut GameRng::new(seed));
+        (map, objects)
+    }
+
+    // what one of the layouts digs out of solid rock
+    fn dig(seed: u64, layout: fn(&mut Map, &mut GameRng) -> Vec<Rect>) -> (Map, Vec<Rect>) {
+        let mut map = vec![vec![Tile::wall(); MAP_HEIGHT as usize]; MAP_WIDTH as usize];
+        let rooms = layout(&mut map, &mut GameRng::new(seed));
+        (map, rooms)
+    }
+
+    // every floor tile can be walked to from every other, and the edge of the
+    // map is left solid
+    fn all_joined_up(map: &Map) -> bool {
+        let floor = |x: i32, y: i32| !map[x as usize][y as usize].blocked;
+        let tiles = || (0..MAP_WIDTH).flat_map(|x| (0..MAP_HEIGHT).map(move |y| (x, y)));
+        let Some(start) = tiles().find(|&(x, y)| floor(x, y)) else {
+            return false;
+        };
+        let reachable = reachable_tiles(map, start);
+        tiles().all(|(x, y)| {
+            let edge = x == 0 || y == 0 || x == MAP_WIDTH - 1 || y == MAP_HEIGHT - 1;
+            !floor(x, y) || (!edge && reachable[x as usize][y as usize])
+        })
+    }
+
+    #[test]
+    fn bsp_rooms_are_all_joined_up() {
+        for seed in 0..200 {
+            let (map, rooms) = dig(seed, dig_bsp);
+            assert!(!rooms.is_empty(), "seed {}: no rooms", seed);
+            assert!(all_joined_up(&map), "seed {}: rooms cut off", seed);
+        }
+    }
+
+    #[test]
+    fn caves_are_one_cave() {
+        for seed in 0..100 {
+            let (map, rooms) = dig(seed, dig_caves);
+            assert!(!rooms.is_empty(), "seed {}: no rooms", seed);
+            assert!(all_joined_up(&map), "seed {}: caves cut off", seed);
+        }
+    }
+
+    #[test]
+    fn cut_off_stairs_get_a_tunnel() {
+        let mut map = vec![vec![Tile::wall(); MAP_HEIGHT as usize]; MAP_WIDTH as usize];
+        create_room(Rect::new(2, 2, 6, 6), &mut map);
+        create_room(Rect::new(40, 20, 6, 6), &mut map);
+        let objects = vec![
+            Object::new(4, 4, '@', "Player", WHITE, true),
+            Object::new(42, 22, '<', "stairs", WHITE, false),
+        ];
+        assert!(!reachable_tiles(&map, (4, 4))[42][22]);
+        connect_unreachable(&mut map, &objects, &[]);
+        assert!(reachable_tiles(&map, (4, 4))[42][22]);
+    }
+
+    #[test]
+    fn stairs_keys_and_items_can_be_reached() {
+        for seed in 0..40 {
+            for level in 1..=10 {
+                let (map, objects) = generate(seed, level);
+                let start = objects[PLAYER].pos();
+                // what's behind the locked doors, once they're opened
+                let mut unlocked = map.clone();
+                for tile in unlocked.iter_mut().flatten() {
+                    if tile.door == Some(Door::Locked) {
+                        tile.door = Some(Door::Closed);
+                    }
+                }
+                let reachable = reachable_tiles(&map, start);
+                let with_keys = reachable_tiles(&unlocked, start);
+                let wanted = objects
+                    .iter()
+                    .filter(|object| object.name == "stairs" || object.item.is_some());
+                for object in wanted {
+                    // the way down and the keys have to be got to without a key
+                    let needs_key = object.name != "stairs" && object.item != Some(Item::Key);
+                    let tiles = if needs_key { &with_keys } else { &reachable };
+                    let (x, y) = object.pos();
+                    assert!(
+                        tiles[x as usize][y as usize],
+                        "seed {} level {}: {} at ({}, {}) is cut off",
+                        seed, level, object.name, x, y
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn nothing_behind_a_locked_door_can_be_reached() {
+        let mut locked_doors = 0;
+        for seed in 0..100 {
+            for level in [2, 4, 7, 9] {
+                let (map, objects) = generate(seed, level);
+                let reachable = reachable_tiles(&map, objects[PLAYER].pos());
+                for x in 1..MAP_WIDTH - 1 {
+                    for y in 1..MAP_HEIGHT - 1 {
+                        if map[x as usize][y as usize].door != Some(Door::Locked) {
+                            continue;
+                        }
+                        locked_doors += 1;
+                        // the floor on the far side is only to be got to
+                        // through the door
+                        let behind = (x - 1..=x + 1)
+                            .flat_map(|x| (y - 1..=y + 1).map(move |y| (x, y)))
+                            .filter(|&(x, y)| !map[x as usize][y as usize].blocked)
+                            .any(|(x, y)| !reachable[x as usize][y as usize]);
+                        assert!(
+                            behind,
+                            "seed {} level {}: a way around ({}, {})",
+                            seed, level, x, y
+                        );
+                    }
+                }
+            }
+        }
+        assert!(locked_doors > 0);
+    }
+}
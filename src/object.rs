@@ -0,0 +1,461 @@
+use std::cmp;
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::ai::{Ai, Noise};
+use crate::colors::*;
+use crate::combat::{log_attack, resist, roll_attack, DamageKind, Resistances};
+use crate::dice::Dice;
+use crate::item::{AmmoKind, Item};
+use crate::map::{make_item, Door, Map};
+use crate::path::find_path;
+use crate::ranged::RangedAttack;
+use crate::raws::monster_kind;
+use crate::scheduler::{ATTACK_COST, NORMAL_SPEED, TURN_ENERGY};
+use crate::status::StatusEffect;
+use crate::{Game, Messages, TORCH_RADIUS};
+
+// paths longer than this aren't worth following; better to wait nearby
+const MAX_PATH_LENGTH: usize = 25;
+// how far away monsters can hear someone getting hurt
+const COMBAT_NOISE: i32 = 8;
+// a punch
+pub const UNARMED_DAMAGE: Dice = Dice::new(1, 2, 0);
+
+// This is a generic object: the player, a monster, an item, the stairs
+// It's always represented by a character on screen.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Object {
+    pub x: i32,
+    pub y: i32,
+    pub char: char,
+    pub color: Color,
+    pub name: String,
+    pub blocks: bool,
+    pub alive: bool,
+    pub fighter: Option<Fighter>,
+    pub ai: Option<Ai>,
+    pub item: Option<Item>,
+    pub always_visible: bool,
+    pub level: i32,
+    pub equipment: Option<Equipment>,
+    // how far a monster can see, each with its own eyes
+    pub perception: i32,
+    // monsters that can attack from a distance
+    pub ranged: Option<RangedAttack>,
+    // poison, haste, confusion and the like, while they last
+    pub statuses: Vec<StatusEffect>,
+    // how quickly it gathers energy, and how much it has to act with
+    pub speed: i32,
+    pub energy: i32,
+    // how many there are, for items that stack like arrows; 1 for the rest
+    pub count: i32,
+}
+
+impl Object {
+    pub fn new(x: i32, y: i32, char: char, name: &str, color: Color, blocks: bool) -> Self {
+        Object {
+            x,
+            y,
+            char,
+            color,
+            name: name.into(),
+            blocks,
+            alive: false,
+            fighter: None,
+            ai: None,
+            item: None,
+            always_visible: false,
+            level: 1,
+            equipment: None,
+            // as far as the player's torch reaches, until told otherwise
+            perception: TORCH_RADIUS,
+            ranged: None,
+            statuses: vec![],
+            speed: NORMAL_SPEED,
+            // enough to act straight away
+            energy: TURN_ENERGY,
+            count: 1,
+        }
+    }
+
+    // only ammunition stacks, and only with more of the same
+    pub fn stacks_with(&self, other: &Object) -> bool {
+        matches!(self.item, Some(Item::Ammo { .. }))
+            && self.item == other.item
+            && self.name == other.name
+    }
+
+    pub fn pos(&self) -> (i32, i32) {
+        (self.x, self.y)
+    }
+
+    pub fn set_pos(&mut self, x: i32, y: i32) {
+        self.x = x;
+        self.y = y;
+    }
+
+    // return the distance to another object
+    pub fn distance_to(&self, other: &Object) -> f32 {
+        let dx = other.x - self.x;
+        let dy = other.y - self.y;
+        ((dx.pow(2) + dy.pow(2)) as f32).sqrt()
+    }
+
+    // return the distance to some coord
+    pub fn distance(&self, x: i32, y: i32) -> f32 {
+        (((x - self.x).pow(2) + (y - self.y).pow(2)) as f32).sqrt()
+    }
+
+    pub fn take_damage(&mut self, damage: i32, kind: DamageKind, game: &mut Game) -> Option<i32> {
+        // apply damage if possible, as much as gets past its resistances
+        let damage = self.resisted(damage, kind, game);
+        if let Some(fighter) = self.fighter.as_mut() {
+            if damage > 0 {
+                fighter.hp -= damage;
+                // the sound of a fight carries
+                game.noises.push(Noise {
+                    position: self.pos(),
+                    loudness: COMBAT_NOISE,
+                    age: 0,
+                });
+            }
+        }
+        // check for death, call the death function
+        if let Some(fighter) = self.fighter {
+            if fighter.hp <= 0 {
+                self.alive = false;
+                fighter.on_death.callback(self, game);
+                return Some(fighter.xp);
+            }
+        }
+        None
+    }
+
+    pub fn attack(&mut self, target: &mut Object, game: &mut Game) {
+        self.spend_energy(ATTACK_COST);
+        // roll to hit, then for damage, then for the target's armour
+        let dice = self.melee_damage(game);
+        let attack = roll_attack(self, target, dice, DamageKind::Physical, game);
+        log_attack(&self.name, "attacks", &target.name, &attack, WHITE, game);
+        if attack.damage > 0 {
+            self.damage(target, attack.damage, DamageKind::Physical, game);
+        }
+    }
+
+    // hurt the target, however it was done, and take the credit if it dies
+    pub fn damage(&mut self, target: &mut Object, damage: i32, kind: DamageKind, game: &mut Game) {
+        if let Some(xp) = target.take_damage(damage, kind, game) {
+            // yield xp to the player
+            self.fighter.as_mut().unwrap().xp += xp;
+            if target.name == "Player" {
+                game.cause_of_death = Some(self.name.clone());
+            }
+        }
+    }
+
+    pub fn heal(&mut self, amount: i32, game: &Game) {
+        let max_hp = self.max_hp(game);
+        if let Some(ref mut fighter) = self.fighter {
+            fighter.hp += amount;
+            if fighter.hp > max_hp {
+                fighter.hp = max_hp;
+            }
+        }
+    }
+
+    pub fn equip(&mut self, messages: &mut Messages) {
+        if self.item.is_none() {
+            messages.add(format!("Can't equip {:?}.", self), RED);
+            return;
+        };
+        if let Some(ref mut equipment) = self.equipment {
+            if !equipment.equipped {
+                equipment.equipped = true;
+                messages.add(
+                    format!("Equipped {} on {}.", self.name, equipment.slot),
+                    LIGHT_GREEN,
+                );
+            }
+        } else {
+            messages.add(format!("Can't equip {:?}.", self), RED);
+        }
+    }
+
+    pub fn dequip(&mut self, messages: &mut Messages) {
+        if self.item.is_none() {
+            messages.add(format!("Can't dequip {:?}.", self), RED);
+            return;
+        };
+        if let Some(ref mut equipment) = self.equipment {
+            if equipment.equipped {
+                equipment.equipped = false;
+                messages.add(
+                    format!("Dequipped {} from {}.", self.name, equipment.slot),
+                    LIGHT_YELLOW,
+                );
+            }
+        } else {
+            messages.add(format!("Can't dequip {:?}.", self), RED);
+        }
+    }
+
+    pub fn power(&self, game: &Game) -> i32 {
+        let base_power = self.fighter.map_or(0, |f| f.base_power);
+        let bonus: i32 = self
+            .get_all_equipped(game)
+            .iter()
+            .map(|e| e.power_bonus)
+            .sum();
+        base_power + bonus
+    }
+
+    pub fn defense(&self, game: &Game) -> i32 {
+        let base_defense = self.fighter.map_or(0, |f| f.base_defense);
+        let bonus: i32 = self
+            .get_all_equipped(game)
+            .iter()
+            .map(|e| e.defense_bonus)
+            .sum();
+        base_defense + bonus
+    }
+
+    pub fn max_hp(&self, game: &Game) -> i32 {
+        let base_max_hp = self.fighter.map_or(0, |f| f.base_max_hp);
+        let bonus: i32 = self
+            .get_all_equipped(game)
+            .iter()
+            .map(|e| e.max_hp_bonus)
+            .sum();
+        base_max_hp + bonus
+    }
+
+    // what it hits with: its weapon, or failing that its fists (or claws,
+    // or teeth), and all the power behind them
+    pub fn melee_damage(&self, game: &Game) -> Dice {
+        let dice = self
+            .get_all_equipped(game)
+            .iter()
+            .filter(|e| e.launcher.is_none())
+            .find_map(|e| e.damage)
+            .or(self.fighter.map(|f| f.damage))
+            .unwrap_or(UNARMED_DAMAGE);
+        Dice::new(dice.count, dice.sides, dice.bonus + self.power(game))
+    }
+
+    // its own resistance to the kind of damage, and what its equipment adds
+    pub fn resistance(&self, kind: DamageKind, game: &Game) -> i32 {
+        let base = self.fighter.map_or(0, |f| f.resistances.get(kind));
+        let bonus: i32 = self
+            .get_all_equipped(game)
+            .iter()
+            .map(|e| e.resistances.get(kind))
+            .sum();
+        base + bonus
+    }
+
+    // how much of the damage would get through
+    pub fn resisted(&self, damage: i32, kind: DamageKind, game: &Game) -> i32 {
+        resist(damage, self.resistance(kind, game))
+    }
+
+    pub fn accuracy(&self) -> i32 {
+        self.fighter.map_or(0, |f| f.accuracy)
+    }
+
+    pub fn evasion(&self) -> i32 {
+        self.fighter.map_or(0, |f| f.evasion)
+    }
+
+    // returns a list of equipped items
+    pub fn get_all_equipped(&self, game: &Game) -> Vec<Equipment> {
+        if self.name == "Player" {
+            game.inventory
+                .iter()
+                .filter(|item| item.equipment.is_some_and(|e| e.equipped))
+                .map(|item| item.equipment.unwrap())
+                .collect()
+        } else {
+            vec![] // other objects have no equipment
+        }
+    }
+}
+
+// move by the given amount, if the destination is not blocked. walking into a
+// closed door opens it instead
+pub fn move_by(id: usize, dx: i32, dy: i32, map: &mut Map, objects: &mut [Object]) {
+    let (x, y) = objects[id].pos();
+    let tile = &mut map[(x + dx) as usize][(y + dy) as usize];
+    if tile.door == Some(Door::Closed) {
+        tile.set_door(Door::Open);
+    } else if !is_blocked(x + dx, y + dy, map, objects) {
+        objects[id].set_pos(x + dx, y + dy);
+    }
+}
+
+pub fn move_towards(id: usize, target_x: i32, target_y: i32, map: &mut Map, objects: &mut [Object]) {
+    // vector from this object to the target, and distance
+    let dx = target_x - objects[id].x;
+    let dy = target_y - objects[id].y;
+    let distance = ((dx.pow(2) + dy.pow(2)) as f32).sqrt();
+
+    // normalize it to length 1 (preserving direction), then round it and
+    // convert to integer so the movement is restricted to the map grid
+    let dx = (dx as f32 / distance).round() as i32;
+    let dy = (dy as f32 / distance).round() as i32;
+    move_by(id, dx, dy, map, objects);
+}
+
+// step along the shortest path to the target, going around walls and other
+// monsters. when there's no way through, or it's a long way round, just head
+// straight for it like `move_towards`
+pub fn move_astar(id: usize, target_x: i32, target_y: i32, map: &mut Map, objects: &mut [Object]) {
+    match find_path(objects[id].pos(), (target_x, target_y), map, objects) {
+        Some(path) if path.len() <= MAX_PATH_LENGTH => {
+            let (x, y) = objects[id].pos();
+            let (next_x, next_y) = path[0];
+            move_by(id, next_x - x, next_y - y, map, objects);
+        }
+        _ => move_towards(id, target_x, target_y, map, objects),
+    }
+}
+
+// Mutably borrow two *separate* elements from the given slice.
+// Panics when the indexes are equal or out of bounds.
+pub fn mut_two<T>(first_index: usize, second_index: usize, items: &mut [T]) -> (&mut T, &mut T) {
+    assert!(first_index != second_index);
+    let split_at_index = cmp::max(first_index, second_index);
+    let (first_slice, second_slice) = items.split_at_mut(split_at_index);
+    if first_index < second_index {
+        (&mut first_slice[first_index], &mut second_slice[0])
+    } else {
+        (&mut second_slice[0], &mut first_slice[second_index])
+    }
+}
+
+pub fn is_blocked(x: i32, y: i32, map: &Map, objects: &[Object]) -> bool {
+    // first test the map tile
+    if map[x as usize][y as usize].blocked {
+        return true;
+    }
+    // now check for any blocking objects
+    objects
+        .iter()
+        .any(|object| object.blocks && object.pos() == (x, y))
+}
+
+// combat-related properties and methods (monster, player, NPC).
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Fighter {
+    pub base_max_hp: i32,
+    pub hp: i32,
+    pub base_defense: i32,
+    pub base_power: i32,
+    pub xp: i32,
+    pub on_death: DeathCallback,
+    // how good it is at landing blows, and at dodging them
+    pub accuracy: i32,
+    pub evasion: i32,
+    // what it does with its bare hands, or whatever nature gave it
+    pub damage: Dice,
+    pub resistances: Resistances,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum DeathCallback {
+    Player,
+    Monster,
+}
+
+impl DeathCallback {
+    fn callback(self, object: &mut Object, game: &mut Game) {
+        use DeathCallback::*;
+        let callback = match self {
+            Player => player_death,
+            Monster => monster_death,
+        };
+        callback(object, game);
+    }
+}
+
+fn player_death(player: &mut Object, game: &mut Game) {
+    game.messages.add("You died!", RED);
+    player.char = '%';
+    player.color = DARK_RED;
+}
+
+fn monster_death(monster: &mut Object, game: &mut Game) {
+    game.messages.add(
+        format!(
+            "The {} is dead! You gained {} EXP.",
+            monster.name,
+            monster.fighter.unwrap().xp,
+        ),
+        ORANGE,
+    );
+    // leave something behind, sometimes
+    if let Some(kind) = monster_kind(&monster.name) {
+        for drop in &kind.drops {
+            if game.rng.gen_range(0, 100) < drop.chance {
+                game.dropped.push(make_item(&drop.item, monster.x, monster.y));
+            }
+        }
+    }
+    monster.char = '%';
+    monster.color = DARK_RED;
+    monster.blocks = false;
+    monster.fighter = None;
+    monster.ai = None;
+    monster.name = format!("remains of {}", monster.name);
+}
+
+// An object that can be equipped
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Equipment {
+    pub slot: Slot,
+    #[serde(default)]
+    pub equipped: bool,
+    pub max_hp_bonus: i32,
+    pub power_bonus: i32,
+    pub defense_bonus: i32,
+    // weapons' damage, used instead of the wielder's own
+    #[serde(default)]
+    pub damage: Option<Dice>,
+    // against fire, cold and the like, for as long as it's worn
+    #[serde(default)]
+    pub resistances: Resistances,
+    // for bows and crossbows, whose damage is done by what they shoot
+    #[serde(default)]
+    pub launcher: Option<Launcher>,
+    // balanced for throwing: does its damage to whoever it's thrown at
+    #[serde(default)]
+    pub throwing: bool,
+}
+
+// What a bow or a crossbow shoots, and how far.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Launcher {
+    pub ammo: AmmoKind,
+    pub range: i32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Slot {
+    LeftHand,
+    RightHand,
+    Head,
+    // slung over the shoulder, ready to shoot
+    Back,
+}
+
+impl std::fmt::Display for Slot {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            Slot::LeftHand => write!(f, "Left Hand"),
+            Slot::RightHand => write!(f, "Right Hand"),
+            Slot::Head => write!(f, "Head"),
+            Slot::Back => write!(f, "Back"),
+        }
+    }
+}
@@ -0,0 +1,201 @@
+use std::cmp::{self, Reverse};
+use std::collections::BinaryHeap;
+
+use crate::map::{Door, Map, MAP_HEIGHT, MAP_WIDTH};
+use crate::object::Object;
+
+// what it costs to walk into a tile that's free, and the extra turn spent
+// opening a closed door first
+const STEP_COST: u32 = 1;
+const DOOR_COST: u32 = 1;
+
+// The shortest path from `from` to `to`, moving in all eight directions like
+// everything in the dungeon does. Walls, locked doors and blocking objects are
+// in the way (apart from whatever stands on `to` itself, which is usually
+// what is being chased). Returns the steps after `from`, ending with `to`, or
+// None if there's no way there.
+pub fn find_path(
+    from: (i32, i32),
+    to: (i32, i32),
+    map: &Map,
+    objects: &[Object],
+) -> Option<Vec<(i32, i32)>> {
+    let index = |(x, y): (i32, i32)| (x + y * MAP_WIDTH) as usize;
+    let size = (MAP_WIDTH * MAP_HEIGHT) as usize;
+
+    let mut occupied = vec![false; size];
+    for object in objects.iter().filter(|object| object.blocks) {
+        occupied[index(object.pos())] = true;
+    }
+    // the cost of moving into a tile, or None if it can't be walked into
+    let cost = |(x, y): (i32, i32)| {
+        let cost = step_cost(map, x, y)?;
+        if (x, y) != to && occupied[index((x, y))] {
+            return None;
+        }
+        Some(cost)
+    };
+    // diagonal moves cost the same as straight ones, so the number of moves
+    // left is at least the longer of the two distances
+    let estimate = |(x, y): (i32, i32)| cmp::max((x - to.0).abs(), (y - to.1).abs()) as u32;
+
+    let mut best_cost = vec![u32::MAX; size];
+    let mut came_from = vec![None; size];
+    let mut open = BinaryHeap::new();
+    best_cost[index(from)] = 0;
+    open.push(Reverse((estimate(from), 0, from)));
+
+    while let Some(Reverse((_, cost_so_far, current))) = open.pop() {
+        if current == to {
+            // walk back to the start to find the way
+            let mut path = vec![to];
+            let mut step = to;
+            while let Some(previous) = came_from[index(step)] {
+                if previous == from {
+                    break;
+                }
+                path.push(previous);
+                step = previous;
+            }
+            path.reverse();
+            return Some(path);
+        }
+        if cost_so_far > best_cost[index(current)] {
+            // already found a cheaper way here
+            continue;
+        }
+        for dx in -1..=1 {
+            for dy in -1..=1 {
+                let next = (current.0 + dx, current.1 + dy);
+                if next == current {
+                    continue;
+                }
+                if let Some(step_cost) = cost(next) {
+                    let next_cost = cost_so_far + step_cost;
+                    if next_cost < best_cost[index(next)] {
+                        best_cost[index(next)] = next_cost;
+                        came_from[index(next)] = Some(current);
+                        open.push(Reverse((next_cost + estimate(next), next_cost, next)));
+                    }
+                }
+            }
+        }
+    }
+    None
+}
+
+// the cost of walking into a tile, ignoring whoever might be standing there,
+// or None if it's out of the map or can't be walked into at all
+pub fn step_cost(map: &Map, x: i32, y: i32) -> Option<u32> {
+    if x < 0 || y < 0 || x >= MAP_WIDTH || y >= MAP_HEIGHT {
+        return None;
+    }
+    let tile = &map[x as usize][y as usize];
+    match tile.door {
+        Some(Door::Locked) => None,
+        Some(Door::Closed) => Some(STEP_COST + DOOR_COST),
+        _ if tile.blocked => None,
+        _ => Some(STEP_COST),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::colors::WHITE;
+    use crate::map::Tile;
+
+    // solid rock, with a room dug out from (1, 1) to (10, 10)
+    fn room() -> Map {
+        let mut map = vec![vec![Tile::wall(); MAP_HEIGHT as usize]; MAP_WIDTH as usize];
+        for column in &mut map[1..=10] {
+            for tile in &mut column[1..=10] {
+                *tile = Tile::empty();
+            }
+        }
+        map
+    }
+
+    // a wall across the room at x = 5, with a gap at the bottom
+    fn divided_room() -> Map {
+        let mut map = room();
+        map[5][1..=9].fill(Tile::wall());
+        map
+    }
+
+    fn blocker(x: i32, y: i32) -> Object {
+        Object::new(x, y, 'o', "orc", WHITE, true)
+    }
+
+    #[test]
+    fn walks_straight_there() {
+        let mut map = vec![vec![Tile::wall(); MAP_HEIGHT as usize]; MAP_WIDTH as usize];
+        for column in &mut map[1..=10] {
+            column[2] = Tile::empty();
+        }
+        let path = find_path((2, 2), (6, 2), &map, &[]).unwrap();
+        assert_eq!(path, [(3, 2), (4, 2), (5, 2), (6, 2)]);
+        // across a room there's more than one way, all as short
+        let path = find_path((2, 2), (6, 2), &room(), &[]).unwrap();
+        assert_eq!(path.len(), 4);
+        assert_eq!(path.last(), Some(&(6, 2)));
+    }
+
+    #[test]
+    fn cuts_corners() {
+        let path = find_path((2, 2), (6, 6), &room(), &[]).unwrap();
+        assert_eq!(path.len(), 4);
+        assert_eq!(path.last(), Some(&(6, 6)));
+    }
+
+    #[test]
+    fn goes_around_walls() {
+        let path = find_path((3, 2), (7, 2), &divided_room(), &[]).unwrap();
+        assert!(path.contains(&(5, 10)));
+        assert_eq!(path.last(), Some(&(7, 2)));
+    }
+
+    #[test]
+    fn no_way_through() {
+        let mut map = divided_room();
+        map[5][10] = Tile::wall();
+        assert_eq!(find_path((3, 2), (7, 2), &map, &[]), None);
+        // or into the rock
+        assert_eq!(find_path((3, 2), (0, 0), &room(), &[]), None);
+    }
+
+    #[test]
+    fn opens_closed_doors_but_not_locked_ones() {
+        let mut map = divided_room();
+        map[5][10] = Tile::door(Door::Closed);
+        let path = find_path((3, 2), (7, 2), &map, &[]).unwrap();
+        assert!(path.contains(&(5, 10)));
+        map[5][10] = Tile::door(Door::Locked);
+        assert_eq!(find_path((3, 2), (7, 2), &map, &[]), None);
+    }
+
+    #[test]
+    fn goes_around_a_closed_door_if_that_is_quicker() {
+        // one door right on the way, and an open gap just beside it
+        let mut map = room();
+        map[5][1..=10].fill(Tile::wall());
+        map[5][5] = Tile::door(Door::Closed);
+        map[5][6] = Tile::empty();
+        let path = find_path((3, 5), (7, 5), &map, &[]).unwrap();
+        assert!(path.contains(&(5, 6)) && !path.contains(&(5, 5)));
+    }
+
+    #[test]
+    fn steps_around_whoever_is_in_the_way() {
+        let objects = [blocker(4, 2), blocker(6, 2)];
+        let path = find_path((2, 2), (6, 2), &room(), &objects).unwrap();
+        // not through the first, but right up to the one being chased
+        assert!(!path.contains(&(4, 2)));
+        assert_eq!(path.last(), Some(&(6, 2)));
+        // and not at all when there's no room to get past
+        let mut map = room();
+        map[4][1..=10].fill(Tile::wall());
+        map[4][2] = Tile::empty();
+        assert_eq!(find_path((2, 2), (6, 2), &map, &objects), None);
+    }
+}
@@ -0,0 +1,187 @@
+use serde::{Deserialize, Serialize};
+
+use crate::colors::*;
+use crate::combat::{log_attack, roll_attack, DamageKind};
+use crate::dice::Dice;
+use crate::fov::line;
+use crate::item::{target_monster, Item};
+use crate::map::{make_item, Map};
+use crate::object::{mut_two, Object};
+use crate::scheduler::ATTACK_COST;
+use crate::{Game, Ui, PLAYER};
+
+// A monster's way of hurting the player from a distance: arrows, spells,
+// breath.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RangedAttack {
+    // what the attack looks like, as in "The Goblin archer <verb> Player"
+    pub verb: String,
+    pub range: i32,
+    // how far from the player the monster would rather stay; it backs off
+    // when they get closer, for as long as it can still attack
+    pub preferred_distance: i32,
+    pub damage: Dice,
+    // arrows are physical, firebolts fire
+    #[serde(default)]
+    pub kind: DamageKind,
+    // shots left, or None if it never runs out
+    pub ammo: Option<i32>,
+    // how many turns from one attack to the next (0 or 1 for every turn),
+    // and how many are still to go before the next
+    #[serde(default)]
+    pub cooldown: i32,
+    #[serde(default)]
+    pub recharge: i32,
+}
+
+impl RangedAttack {
+    pub fn new(verb: &str, range: i32, preferred_distance: i32, damage: Dice) -> Self {
+        RangedAttack {
+            verb: verb.into(),
+            range,
+            preferred_distance,
+            damage,
+            kind: DamageKind::Physical,
+            ammo: None,
+            cooldown: 0,
+            recharge: 0,
+        }
+    }
+
+    // whether there's anything left to attack with, now or later
+    pub fn has_ammo(&self) -> bool {
+        self.ammo.is_none_or(|ammo| ammo > 0)
+    }
+
+    pub fn ready(&self) -> bool {
+        self.has_ammo() && self.recharge == 0
+    }
+}
+
+// whether something at `from` could hit `to` in a straight line: nothing
+// that blocks, neither walls nor anyone standing in between
+pub fn line_of_fire(from: (i32, i32), to: (i32, i32), map: &Map, objects: &[Object]) -> bool {
+    line(from, to)
+        .into_iter()
+        .take_while(|&tile| tile != to)
+        .all(|(x, y)| {
+            !map[x as usize][y as usize].blocked
+                && !objects
+                    .iter()
+                    .any(|object| object.blocks && object.pos() == (x, y))
+        })
+}
+
+// the attacker uses its ranged attack on the target, which must be in range
+// and in the line of fire
+pub fn ranged_attack(
+    attacker_id: usize,
+    target_id: usize,
+    game: &mut Game,
+    objects: &mut [Object],
+) {
+    let (attacker, target) = mut_two(attacker_id, target_id, objects);
+    let ranged = match attacker.ranged.as_mut() {
+        Some(ranged) => ranged,
+        None => return,
+    };
+    if let Some(ammo) = ranged.ammo.as_mut() {
+        *ammo -= 1;
+    }
+    ranged.recharge = ranged.cooldown;
+    let (verb, dice, kind) = (ranged.verb.clone(), ranged.damage, ranged.kind);
+    attacker.spend_energy(ATTACK_COST);
+
+    let attack = roll_attack(attacker, target, dice, kind, game);
+    log_attack(&attacker.name, &verb, &target.name, &attack, ORANGE, game);
+    if attack.damage > 0 {
+        attacker.damage(target, attack.damage, kind, game);
+    }
+}
+
+// the player shoots their bow (or crossbow) at a monster they pick. the shot
+// flies along the line towards it and stops at the first thing in the way,
+// which may not be what they were aiming at. a shot that misses, or hits a
+// wall, lands on the floor to be picked up again. returns whether anything
+// was fired
+pub fn fire(ui: &mut dyn Ui, game: &mut Game, objects: &mut [Object]) -> bool {
+    let launcher = game
+        .inventory
+        .iter()
+        .filter(|item| item.equipment.is_some_and(|e| e.equipped))
+        .find_map(|item| {
+            let equipment = item.equipment?;
+            Some((item.name.clone(), equipment.launcher?, equipment.damage?))
+        });
+    let (weapon, launcher, dice) = match launcher {
+        Some(launcher) => launcher,
+        None => {
+            game.messages.add("You have nothing to shoot with.", RED);
+            return false;
+        }
+    };
+    let ammo_item = Some(Item::Ammo {
+        ammo: launcher.ammo,
+    });
+    let ammo_id = match game.inventory.iter().position(|item| item.item == ammo_item) {
+        Some(ammo_id) => ammo_id,
+        None => {
+            game.messages
+                .add(format!("You have nothing to shoot from your {}.", weapon), RED);
+            return false;
+        }
+    };
+
+    // ask the player for a target
+    game.messages.add(
+        "Left-click an enemy to shoot at, or right-click to cancel.",
+        LIGHT_CYAN,
+    );
+    let target_id = match target_monster(ui, game, objects, Some(launcher.range as f32)) {
+        Some(target_id) => target_id,
+        None => {
+            game.messages.add("Cancelled", WHITE);
+            return false;
+        }
+    };
+
+    // one off the stack
+    let ammo = game.inventory[ammo_id].name.clone();
+    game.inventory[ammo_id].count -= 1;
+    if game.inventory[ammo_id].count == 0 {
+        game.inventory.remove(ammo_id);
+    }
+    objects[PLAYER].spend_energy(ATTACK_COST);
+
+    let from = objects[PLAYER].pos();
+    let to = objects[target_id].pos();
+    let mut landing = from;
+    for (x, y) in line(from, to) {
+        if game.map[x as usize][y as usize].blocked {
+            game.messages.add("Your shot hits the wall.", WHITE);
+            break;
+        }
+        let hit_id = objects
+            .iter()
+            .position(|object| object.blocks && object.fighter.is_some() && object.pos() == (x, y));
+        landing = (x, y);
+        if let Some(hit_id) = hit_id {
+            let (player, hit) = mut_two(PLAYER, hit_id, objects);
+            let attack = roll_attack(player, hit, dice, DamageKind::Physical, game);
+            log_attack(&player.name, "shoots", &hit.name, &attack, WHITE, game);
+            if !attack.hit {
+                break;
+            }
+            if attack.damage > 0 {
+                player.damage(hit, attack.damage, DamageKind::Physical, game);
+            }
+            // it's stuck in them, or broken
+            return true;
+        }
+    }
+    let (x, y) = landing;
+    let mut shot = make_item(&ammo, x, y);
+    shot.count = 1;
+    game.dropped.push(shot);
+    true
+}
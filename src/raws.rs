@@ -0,0 +1,653 @@
+use std::collections::HashSet;
+use std::fs;
+use std::sync::OnceLock;
+
+use serde::Deserialize;
+
+use crate::colors::Color;
+use crate::combat::Resistances;
+use crate::dice::Dice;
+use crate::item::Item;
+use crate::map::{from_dungeon_level, Transition};
+use crate::object::Equipment;
+use crate::ranged::RangedAttack;
+use crate::scheduler::NORMAL_SPEED;
+
+// the bestiary and everything that can be picked up, kept as data so they
+// can be changed without touching the code
+pub const MONSTERS_FILE: &str = "monsters.json";
+pub const ITEMS_FILE: &str = "items.json";
+
+// the items the game makes by name, whatever else is in ITEMS_FILE: what the
+// player starts with, and what opens a locked vault
+pub const STARTING_WEAPON: &str = "Dagger";
+pub const KEY: &str = "Key";
+
+// Everything that makes one kind of monster what it is, as written in
+// MONSTERS_FILE.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MonsterKind {
+    pub name: String,
+    pub glyph: char,
+    pub color: Color,
+    pub hp: i32,
+    pub defense: i32,
+    pub power: i32,
+    // its bite, claws or fists, and how good it is at hitting with them and
+    // at getting out of the way
+    pub damage: Dice,
+    #[serde(default)]
+    pub accuracy: i32,
+    #[serde(default)]
+    pub evasion: i32,
+    // what it shrugs off, and what it's weak to
+    #[serde(default)]
+    pub resistances: Resistances,
+    pub xp: i32,
+    // how far it can see
+    pub perception: i32,
+    // how quickly it gets to act, against NORMAL_SPEED
+    #[serde(default = "normal_speed")]
+    pub speed: i32,
+    #[serde(default)]
+    pub ai: AiKind,
+    #[serde(default)]
+    pub ranged: Option<RangedAttack>,
+    // how likely it is to turn up, against the other monsters, from each
+    // dungeon level on
+    pub spawn: Vec<Transition>,
+    #[serde(default)]
+    pub drops: Vec<DropChance>,
+}
+
+fn normal_speed() -> i32 {
+    NORMAL_SPEED
+}
+
+fn one() -> i32 {
+    1
+}
+
+// What a monster is doing when the player first comes across it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AiKind {
+    // mostly asleep, though some are up and about
+    #[default]
+    Sleeps,
+    // always roaming the level
+    Wanders,
+}
+
+// Something a monster leaves behind when it dies, some of the time.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DropChance {
+    // the name of one of the items in ITEMS_FILE
+    pub item: String,
+    // in percent
+    pub chance: u32,
+}
+
+// Everything that makes one kind of item what it is, as written in
+// ITEMS_FILE.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ItemKind {
+    pub name: String,
+    pub glyph: char,
+    pub color: Color,
+    pub effect: Item,
+    // for the ones that can be worn or wielded
+    #[serde(default)]
+    pub equipment: Option<Equipment>,
+    // how many come together, for the ones that stack
+    #[serde(default = "one")]
+    pub count: i32,
+    // how likely it is to be lying around, against the other items, from
+    // each dungeon level on. empty for the ones that are only ever given
+    // out, like keys
+    #[serde(default)]
+    pub spawn: Vec<Transition>,
+}
+
+// what came of reading each file: it's only ever read once, mistakes and all
+static MONSTERS: OnceLock<Result<Vec<MonsterKind>, String>> = OnceLock::new();
+static ITEMS: OnceLock<Result<Vec<ItemKind>, String>> = OnceLock::new();
+
+// read and check both files, or say what's wrong with the first broken one.
+// there's no game without them, so this is done before anything else
+pub fn load_raws() -> Result<(), String> {
+    load_items()?;
+    load_monsters()?;
+    Ok(())
+}
+
+// every kind of monster in MONSTERS_FILE, read the first time they're asked
+// for
+pub fn load_monsters() -> Result<&'static [MonsterKind], String> {
+    let monsters = MONSTERS.get_or_init(|| {
+        read_raws(MONSTERS_FILE)
+            .and_then(|text| parse_monsters(&text))
+            .and_then(|monsters| check_drops(&monsters, load_items()?).map(|_| monsters))
+            .map_err(|e| format!("{}: {}", MONSTERS_FILE, e))
+    });
+    monsters.as_deref().map_err(Clone::clone)
+}
+
+// the same for every kind of item in ITEMS_FILE
+pub fn load_items() -> Result<&'static [ItemKind], String> {
+    let items = ITEMS.get_or_init(|| {
+        read_raws(ITEMS_FILE)
+            .and_then(|text| parse_items(&text))
+            .and_then(|items| check_required(&items).map(|_| items))
+            .map_err(|e| format!("{}: {}", ITEMS_FILE, e))
+    });
+    items.as_deref().map_err(Clone::clone)
+}
+
+// for the game itself, which only ever runs once `load_raws` has succeeded
+pub fn monsters() -> &'static [MonsterKind] {
+    load_monsters().unwrap_or_else(|e| panic!("{}", e))
+}
+
+pub fn items() -> &'static [ItemKind] {
+    load_items().unwrap_or_else(|e| panic!("{}", e))
+}
+
+fn read_raws(path: &str) -> Result<String, String> {
+    fs::read_to_string(path).map_err(|e| format!("could not be read: {}", e))
+}
+
+pub fn monster_kind(name: &str) -> Option<&'static MonsterKind> {
+    monsters().iter().find(|kind| kind.name == name)
+}
+
+pub fn item_kind(name: &str) -> Option<&'static ItemKind> {
+    items().iter().find(|kind| kind.name == name)
+}
+
+// read the bestiary and check that it makes sense, so mistakes are caught
+// before the first monster is made
+pub fn parse_monsters(text: &str) -> Result<Vec<MonsterKind>, String> {
+    let monsters: Vec<MonsterKind> = serde_json::from_str(text).map_err(|e| e.to_string())?;
+    if monsters.is_empty() {
+        return Err("there are no monsters".into());
+    }
+    let mut names = HashSet::new();
+    for monster in &monsters {
+        let error = |problem: &str| format!("monster `{}`: {}", monster.name, problem);
+        if monster.name.is_empty() {
+            return Err("a monster has no name".into());
+        }
+        if !names.insert(&monster.name) {
+            return Err(error("there's another monster with the same name"));
+        }
+        if monster.hp <= 0 {
+            return Err(error("`hp` should be more than 0"));
+        }
+        if monster.speed <= 0 {
+            return Err(error("`speed` should be more than 0"));
+        }
+        if monster.defense < 0 || monster.power < 0 || monster.xp < 0 || monster.perception < 0 {
+            return Err(error(
+                "`defense`, `power`, `xp` and `perception` can't be negative",
+            ));
+        }
+        check_transitions(&monster.spawn).map_err(|e| error(&format!("`spawn` {}", e)))?;
+        if let Some(ranged) = &monster.ranged {
+            if ranged.range <= 0 {
+                return Err(error("the ranged attack's `range` should be more than 0"));
+            }
+            if ranged.preferred_distance > ranged.range {
+                return Err(error(
+                    "the ranged attack's `preferred_distance` is out of its range",
+                ));
+            }
+            if ranged.ammo.is_some_and(|ammo| ammo < 0) || ranged.cooldown < 0 {
+                return Err(error(
+                    "the ranged attack's `ammo` and `cooldown` can't be negative",
+                ));
+            }
+        }
+        if monster.drops.iter().any(|drop| drop.chance > 100) {
+            return Err(error("a drop `chance` is a percentage, 100 at most"));
+        }
+    }
+    check_every_level(monsters.iter().map(|monster| &monster.spawn[..]))
+        .map_err(|level| format!("no monster can spawn on dungeon level {}", level))?;
+    Ok(monsters)
+}
+
+pub fn parse_items(text: &str) -> Result<Vec<ItemKind>, String> {
+    let items: Vec<ItemKind> = serde_json::from_str(text).map_err(|e| e.to_string())?;
+    let mut names = HashSet::new();
+    for item in &items {
+        let error = |problem: &str| format!("item `{}`: {}", item.name, problem);
+        if item.name.is_empty() {
+            return Err("an item has no name".into());
+        }
+        if !names.insert(&item.name) {
+            return Err(error("there's another item with the same name"));
+        }
+        let in_range = |value: i32| value > 0;
+        let makes_sense = match item.effect {
+            Item::Heal { amount } => in_range(amount),
+            Item::DamageNearest { damage, range, .. } => in_range(damage) && in_range(range),
+            Item::AreaDamage { damage, radius, .. } => in_range(damage) && radius >= 0,
+            Item::Status {
+                num_turns, range, ..
+            } => in_range(num_turns) && in_range(range),
+            Item::SelfStatus { num_turns, .. } => in_range(num_turns),
+            Item::Equip | Item::Key | Item::Ammo { .. } => true,
+        };
+        if !makes_sense {
+            return Err(error("the effect's numbers should be more than 0"));
+        }
+        if (item.effect == Item::Equip) != item.equipment.is_some() {
+            return Err(error(
+                "items with `equipment` should have the `equip` effect, and the other way round",
+            ));
+        }
+        if item.count <= 0 {
+            return Err(error("`count` should be more than 0"));
+        }
+        if item.count > 1 && !matches!(item.effect, Item::Ammo { .. }) {
+            return Err(error("only ammunition comes more than one at a time"));
+        }
+        if let Some(equipment) = item.equipment {
+            if equipment
+                .launcher
+                .is_some_and(|launcher| launcher.range <= 0)
+            {
+                return Err(error("the launcher's `range` should be more than 0"));
+            }
+            if equipment.launcher.is_some() && equipment.damage.is_none() {
+                return Err(error("a launcher needs `damage` dice for what it shoots"));
+            }
+        }
+        check_transitions(&item.spawn).map_err(|e| error(&format!("`spawn` {}", e)))?;
+    }
+    check_every_level(items.iter().map(|item| &item.spawn[..]))
+        .map_err(|level| format!("no item can turn up on dungeon level {}", level))?;
+    Ok(items)
+}
+
+// every item a monster can drop has to exist
+fn check_drops(monsters: &[MonsterKind], items: &[ItemKind]) -> Result<(), String> {
+    for monster in monsters {
+        for drop in &monster.drops {
+            if !items.iter().any(|item| item.name == drop.item) {
+                return Err(format!(
+                    "monster `{}`: there's no item called `{}` in {}",
+                    monster.name, drop.item, ITEMS_FILE
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+// the items the game makes by name are there, and do what it expects
+fn check_required(items: &[ItemKind]) -> Result<(), String> {
+    for (name, effect, effect_name) in [
+        (STARTING_WEAPON, Item::Equip, "equip"),
+        (KEY, Item::Key, "key"),
+    ] {
+        let item = items
+            .iter()
+            .find(|item| item.name == name)
+            .ok_or_else(|| format!("there's no item called `{}`, and the game needs one", name))?;
+        if item.effect != effect {
+            return Err(format!(
+                "item `{}`: should have the `{}` effect",
+                name, effect_name
+            ));
+        }
+    }
+    Ok(())
+}
+
+// every level needs something to put in it, or returns the first that has
+// nothing. past the last level in any table the chances don't change any more
+fn check_every_level<'a>(
+    tables: impl Iterator<Item = &'a [Transition]> + Clone,
+) -> Result<(), u32> {
+    let deepest = tables
+        .clone()
+        .flat_map(|table| table.iter().map(|transition| transition.level))
+        .max()
+        .unwrap_or(1);
+    for level in 1..=deepest {
+        if tables
+            .clone()
+            .all(|table| from_dungeon_level(table, level) == 0)
+        {
+            return Err(level);
+        }
+    }
+    Ok(())
+}
+
+// `from_dungeon_level` needs its levels in order
+pub fn check_transitions(table: &[Transition]) -> Result<(), String> {
+    if table.windows(2).any(|pair| pair[0].level >= pair[1].level) {
+        return Err("levels should go up, one entry each".into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::item::AmmoKind;
+
+    fn orc() -> Value {
+        json!({
+            "name": "Orc",
+            "glyph": "o",
+            "color": { "r": 63, "g": 127, "b": 63 },
+            "hp": 20,
+            "defense": 0,
+            "power": 3,
+            "damage": "1d2",
+            "xp": 35,
+            "perception": 8,
+            "spawn": [{ "level": 1, "value": 80 }]
+        })
+    }
+
+    fn archer() -> Value {
+        let mut archer = orc();
+        archer["name"] = json!("Goblin archer");
+        archer["ranged"] = json!({
+            "verb": "shoots an arrow at",
+            "range": 6,
+            "preferred_distance": 4,
+            "damage": "1d4",
+            "ammo": 10,
+            "cooldown": 2
+        });
+        archer["drops"] = json!([{ "item": "Arrows", "chance": 50 }]);
+        archer
+    }
+
+    fn potion() -> Value {
+        json!({
+            "name": "Healing Potion",
+            "glyph": "!",
+            "color": { "r": 127, "g": 0, "b": 255 },
+            "effect": { "heal": { "amount": 40 } },
+            "spawn": [{ "level": 1, "value": 35 }]
+        })
+    }
+
+    fn bow() -> Value {
+        json!({
+            "name": "Bow",
+            "glyph": "}",
+            "color": { "r": 191, "g": 127, "b": 0 },
+            "effect": "equip",
+            "equipment": {
+                "slot": "Back",
+                "max_hp_bonus": 0,
+                "power_bonus": 0,
+                "defense_bonus": 0,
+                "damage": "1d6",
+                "launcher": { "ammo": "arrow", "range": 8 }
+            }
+        })
+    }
+
+    fn arrows() -> Value {
+        json!({
+            "name": "Arrows",
+            "glyph": "|",
+            "color": { "r": 191, "g": 127, "b": 0 },
+            "effect": { "ammo": { "ammo": "arrow" } },
+            "count": 10
+        })
+    }
+
+    // the value with one of its fields changed
+    fn with(mut value: Value, field: &str, new: Value) -> Value {
+        value[field] = new;
+        value
+    }
+
+    fn monsters_error(monsters: Value) -> String {
+        parse_monsters(&monsters.to_string()).unwrap_err()
+    }
+
+    fn items_error(items: Value) -> String {
+        parse_items(&items.to_string()).unwrap_err()
+    }
+
+    #[test]
+    fn parses_monsters() {
+        let monsters = parse_monsters(&json!([orc(), archer()]).to_string()).unwrap();
+        assert_eq!(monsters.len(), 2);
+        let orc = &monsters[0];
+        assert_eq!(orc.name, "Orc");
+        assert_eq!(orc.damage, Dice::new(1, 2, 0));
+        // what's left out has its defaults
+        assert_eq!(orc.speed, NORMAL_SPEED);
+        assert_eq!(orc.ai, AiKind::Sleeps);
+        assert_eq!(orc.resistances, Resistances::default());
+        assert!(orc.ranged.is_none() && orc.drops.is_empty());
+        let ranged = monsters[1].ranged.as_ref().unwrap();
+        assert_eq!(
+            (ranged.range, ranged.ammo, ranged.cooldown),
+            (6, Some(10), 2)
+        );
+        assert_eq!(monsters[1].drops[0].item, "Arrows");
+    }
+
+    #[test]
+    fn rejects_broken_monsters() {
+        let ranged = |field: &str, value: Value| {
+            let mut archer = archer();
+            archer["ranged"][field] = value;
+            archer
+        };
+        let cases = [
+            (json!([]), "there are no monsters"),
+            (json!([with(orc(), "name", json!(""))]), "a monster has no name"),
+            (
+                json!([orc(), orc()]),
+                "monster `Orc`: there's another monster with the same name",
+            ),
+            (json!([with(orc(), "hp", json!(0))]), "monster `Orc`: `hp` should be more than 0"),
+            (
+                json!([with(orc(), "speed", json!(0))]),
+                "monster `Orc`: `speed` should be more than 0",
+            ),
+            (
+                json!([with(orc(), "xp", json!(-1))]),
+                "monster `Orc`: `defense`, `power`, `xp` and `perception` can't be negative",
+            ),
+            (
+                json!([with(
+                    orc(),
+                    "spawn",
+                    json!([{ "level": 3, "value": 10 }, { "level": 1, "value": 80 }])
+                )]),
+                "monster `Orc`: `spawn` levels should go up, one entry each",
+            ),
+            (
+                json!([ranged("range", json!(0))]),
+                "monster `Goblin archer`: the ranged attack's `range` should be more than 0",
+            ),
+            (
+                json!([ranged("preferred_distance", json!(7))]),
+                "monster `Goblin archer`: the ranged attack's `preferred_distance` is out of its range",
+            ),
+            (
+                json!([ranged("ammo", json!(-1))]),
+                "monster `Goblin archer`: the ranged attack's `ammo` and `cooldown` can't be negative",
+            ),
+            (
+                json!([with(archer(), "drops", json!([{ "item": "Arrows", "chance": 101 }]))]),
+                "monster `Goblin archer`: a drop `chance` is a percentage, 100 at most",
+            ),
+            (
+                json!([with(orc(), "spawn", json!([{ "level": 2, "value": 80 }]))]),
+                "no monster can spawn on dungeon level 1",
+            ),
+        ];
+        for (monsters, error) in cases {
+            assert_eq!(monsters_error(monsters), error);
+        }
+    }
+
+    #[test]
+    fn rejects_monsters_that_arent_monsters() {
+        let error = monsters_error(json!([with(orc(), "wings", json!(2))]));
+        assert!(error.starts_with("unknown field `wings`"), "{}", error);
+        let error = monsters_error(json!([with(orc(), "damage", json!("lots"))]));
+        assert!(
+            error.starts_with("`lots` isn't dice, like `2d6+1`"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn parses_items() {
+        let items = parse_items(&json!([potion(), bow(), arrows()]).to_string()).unwrap();
+        assert_eq!(items.len(), 3);
+        assert_eq!(items[0].effect, Item::Heal { amount: 40 });
+        assert_eq!(items[0].count, 1);
+        let equipment = items[1].equipment.unwrap();
+        assert_eq!(equipment.damage, Some(Dice::new(1, 6, 0)));
+        assert_eq!(
+            equipment.launcher.map(|launcher| launcher.ammo),
+            Some(AmmoKind::Arrow)
+        );
+        // only given out, never lying around
+        assert!(items[1].spawn.is_empty());
+        assert_eq!(items[2].count, 10);
+    }
+
+    #[test]
+    fn rejects_broken_items() {
+        let launcher = |field: &str, value: Value| {
+            let mut bow = bow();
+            bow["equipment"]["launcher"][field] = value;
+            bow
+        };
+        let mut no_damage = bow();
+        no_damage["equipment"]
+            .as_object_mut()
+            .unwrap()
+            .remove("damage");
+        let cases = [
+            (json!([with(potion(), "name", json!(""))]), "an item has no name"),
+            (
+                json!([potion(), potion()]),
+                "item `Healing Potion`: there's another item with the same name",
+            ),
+            (
+                json!([with(potion(), "effect", json!({ "heal": { "amount": 0 } }))]),
+                "item `Healing Potion`: the effect's numbers should be more than 0",
+            ),
+            (
+                json!([potion(), with(bow(), "effect", json!("key"))]),
+                "item `Bow`: items with `equipment` should have the `equip` effect, and the other way round",
+            ),
+            (
+                json!([potion(), with(arrows(), "count", json!(0))]),
+                "item `Arrows`: `count` should be more than 0",
+            ),
+            (
+                json!([with(potion(), "count", json!(3))]),
+                "item `Healing Potion`: only ammunition comes more than one at a time",
+            ),
+            (
+                json!([potion(), launcher("range", json!(0))]),
+                "item `Bow`: the launcher's `range` should be more than 0",
+            ),
+            (
+                json!([potion(), no_damage]),
+                "item `Bow`: a launcher needs `damage` dice for what it shoots",
+            ),
+            (
+                json!([with(
+                    potion(),
+                    "spawn",
+                    json!([{ "level": 2, "value": 35 }, { "level": 2, "value": 10 }])
+                )]),
+                "item `Healing Potion`: `spawn` levels should go up, one entry each",
+            ),
+            (
+                json!([with(potion(), "spawn", json!([{ "level": 3, "value": 35 }]))]),
+                "no item can turn up on dungeon level 1",
+            ),
+        ];
+        for (items, error) in cases {
+            assert_eq!(items_error(items), error);
+        }
+    }
+
+    #[test]
+    fn rejects_items_that_arent_items() {
+        let error = items_error(json!([with(potion(), "effect", json!("explode"))]));
+        assert!(error.starts_with("unknown variant `explode`"), "{}", error);
+    }
+
+    #[test]
+    fn drops_have_to_be_items() {
+        let monsters = parse_monsters(&json!([archer()]).to_string()).unwrap();
+        let items = parse_items(&json!([potion(), arrows()]).to_string()).unwrap();
+        assert_eq!(check_drops(&monsters, &items), Ok(()));
+        assert_eq!(
+            check_drops(&monsters, &items[..1]),
+            Err("monster `Goblin archer`: there's no item called `Arrows` in items.json".into())
+        );
+    }
+
+    #[test]
+    fn the_items_the_game_makes_have_to_be_there() {
+        let key = json!({
+            "name": "Key",
+            "glyph": "-",
+            "color": { "r": 255, "g": 191, "b": 0 },
+            "effect": "key"
+        });
+        let dagger = with(bow(), "name", json!("Dagger"));
+        let check = |items: Value| check_required(&parse_items(&items.to_string()).unwrap());
+        assert_eq!(
+            check(json!([potion(), dagger.clone(), key.clone()])),
+            Ok(())
+        );
+        assert_eq!(
+            check(json!([potion(), dagger.clone()])),
+            Err("there's no item called `Key`, and the game needs one".into())
+        );
+        assert_eq!(
+            check(json!([potion(), with(key, "name", json!("Dagger"))])),
+            Err("item `Dagger`: should have the `equip` effect".into())
+        );
+    }
+
+    #[test]
+    fn transitions_go_up_one_level_at_a_time() {
+        let table = |levels: &[u32]| {
+            levels
+                .iter()
+                .map(|&level| Transition { level, value: 10 })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(check_transitions(&table(&[])), Ok(()));
+        assert_eq!(check_transitions(&table(&[1, 3, 5])), Ok(()));
+        for levels in [&[1, 1][..], &[3, 2], &[1, 4, 4]] {
+            assert_eq!(
+                check_transitions(&table(levels)),
+                Err("levels should go up, one entry each".into())
+            );
+        }
+    }
+}
@@ -0,0 +1,350 @@
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+
+use serde::{Deserialize, Serialize};
+
+use crate::map::{MAP_HEIGHT, MAP_WIDTH};
+use crate::object::{Fighter, Object};
+use crate::{new_game, play_command, Command, Game, Ui, PLAYER};
+
+// One thing the player told the game: a command, or the answer to a question
+// the game asked while carrying one out.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Input {
+    Command(Command),
+    Target(Option<(i32, i32)>),
+    LevelUp(usize),
+}
+
+// A recorded game: where it started and everything the player did.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Replay {
+    pub seed: u64,
+    pub inputs: Vec<Input>,
+}
+
+// the first line of a replay file; every line after it is one `Input`
+#[derive(Serialize, Deserialize)]
+struct Header {
+    seed: u64,
+}
+
+// Passes questions through to the real UI and notes down the answers, which
+// are added to the game's inputs once the command is done.
+pub(crate) struct RecordingUi<'a> {
+    ui: &'a mut dyn Ui,
+    answers: Vec<Input>,
+}
+
+impl<'a> RecordingUi<'a> {
+    pub(crate) fn new(ui: &'a mut dyn Ui) -> Self {
+        RecordingUi {
+            ui,
+            answers: vec![],
+        }
+    }
+
+    pub(crate) fn finish(self, game: &mut Game) {
+        game.inputs.extend(self.answers);
+    }
+}
+
+impl Ui for RecordingUi<'_> {
+    fn target_tile(
+        &mut self,
+        game: &Game,
+        objects: &[Object],
+        max_range: Option<f32>,
+    ) -> Option<(i32, i32)> {
+        let target = self.ui.target_tile(game, objects, max_range);
+        self.answers.push(Input::Target(target));
+        target
+    }
+
+    fn level_up_choice(&mut self, fighter: &Fighter) -> usize {
+        let choice = self.ui.level_up_choice(fighter);
+        self.answers.push(Input::LevelUp(choice));
+        choice
+    }
+}
+
+// Answers the game's questions from a recording instead of asking anyone.
+// An answer that doesn't fit the question is noted down, and the question
+// cancelled (or given the first choice) so the command can finish.
+struct ReplayUi {
+    inputs: VecDeque<Input>,
+    // how many inputs have been played, for saying where things went wrong
+    played: usize,
+    error: Option<String>,
+}
+
+impl ReplayUi {
+    fn next(&mut self) -> Option<Input> {
+        let input = self.inputs.pop_front();
+        if input.is_some() {
+            self.played += 1;
+        }
+        input
+    }
+
+    fn out_of_sync(&mut self, expected: &str, found: Option<Input>) {
+        let found = found.map_or("the end of it".into(), |input| format!("{:?}", input));
+        self.error.get_or_insert(format!(
+            "replay out of sync at input {}: expected {}, found {}",
+            self.played, expected, found
+        ));
+    }
+}
+
+impl Ui for ReplayUi {
+    fn target_tile(
+        &mut self,
+        _game: &Game,
+        _objects: &[Object],
+        _max_range: Option<f32>,
+    ) -> Option<(i32, i32)> {
+        match self.next() {
+            Some(Input::Target(None)) => None,
+            Some(Input::Target(Some((x, y))))
+                if (0..MAP_WIDTH).contains(&x) && (0..MAP_HEIGHT).contains(&y) =>
+            {
+                Some((x, y))
+            }
+            other => {
+                self.out_of_sync("a target on the map", other);
+                None
+            }
+        }
+    }
+
+    fn level_up_choice(&mut self, _fighter: &Fighter) -> usize {
+        match self.next() {
+            // one of the three gifts
+            Some(Input::LevelUp(choice)) if choice < 3 => choice,
+            other => {
+                self.out_of_sync("a level up choice", other);
+                0
+            }
+        }
+    }
+}
+
+// Plays a recording back one command at a time, so a frontend can draw the
+// game in between.
+pub struct Replayer {
+    ui: ReplayUi,
+    pub game: Game,
+    pub objects: Vec<Object>,
+}
+
+impl Replayer {
+    pub fn new(replay: Replay) -> Self {
+        let (game, objects) = new_game(replay.seed);
+        Replayer {
+            ui: ReplayUi {
+                inputs: replay.inputs.into(),
+                played: 0,
+                error: None,
+            },
+            game,
+            objects,
+        }
+    }
+
+    // play the next recorded command; false once the recording is over, or
+    // an error if it doesn't fit the game it's being played on
+    pub fn step(&mut self) -> Result<bool, String> {
+        match self.ui.next() {
+            // a step at a time, as anything can be typed in
+            input @ Some(Input::Command(Command::Move(dx, dy))) if dx.abs() > 1 || dy.abs() > 1 => {
+                self.ui.out_of_sync("a move of one step", input);
+            }
+            Some(Input::Command(command)) => {
+                play_command(command, &mut self.ui, &mut self.game, &mut self.objects);
+            }
+            None => return Ok(false),
+            other => self.ui.out_of_sync("a command", other),
+        }
+        match self.ui.error.take() {
+            Some(error) => Err(error),
+            None => Ok(true),
+        }
+    }
+}
+
+// replay a whole game without drawing anything
+pub fn run_headless(replay: Replay) -> Result<(Game, Vec<Object>), String> {
+    let mut replayer = Replayer::new(replay);
+    while replayer.step()? {}
+    Ok((replayer.game, replayer.objects))
+}
+
+// Streams a game's inputs to a file as they happen, so the recording
+// survives a crash.
+pub struct ReplayWriter {
+    file: File,
+    written: usize,
+}
+
+impl ReplayWriter {
+    // start a replay file for `game`, with every input it has seen so far
+    pub fn create(path: &str, game: &Game) -> Result<Self, Box<dyn Error>> {
+        let mut file = File::create(path)?;
+        writeln!(file, "{}", serde_json::to_string(&Header { seed: game.seed })?)?;
+        let mut writer = ReplayWriter { file, written: 0 };
+        writer.update(game)?;
+        Ok(writer)
+    }
+
+    // append the inputs the game has seen since the last call
+    pub fn update(&mut self, game: &Game) -> Result<(), Box<dyn Error>> {
+        for input in &game.inputs[self.written..] {
+            writeln!(self.file, "{}", serde_json::to_string(input)?)?;
+        }
+        self.written = game.inputs.len();
+        self.file.flush()?;
+        Ok(())
+    }
+}
+
+pub fn load_replay(path: &str) -> Result<Replay, Box<dyn Error>> {
+    let mut lines = BufReader::new(File::open(path)?).lines();
+    let header: Header = match lines.next() {
+        Some(line) => serde_json::from_str(&line?)?,
+        None => return Err("empty replay file".into()),
+    };
+    let mut replay = Replay {
+        seed: header.seed,
+        inputs: vec![],
+    };
+    for line in lines {
+        let line = line?;
+        if !line.trim().is_empty() {
+            replay.inputs.push(serde_json::from_str(&line)?);
+        }
+    }
+    Ok(replay)
+}
+
+// a short description of how a game ended up, for headless runs
+pub fn summary(game: &Game, objects: &[Object]) -> String {
+    let player = &objects[PLAYER];
+    format!(
+        "seed {}: {} after {} inputs on dungeon level {}, player level {}, HP {}",
+        game.seed,
+        if player.alive { "alive" } else { "dead" },
+        game.inputs.len(),
+        game.dungeon_level,
+        player.level,
+        player.fighter.map_or(0, |f| f.hp),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::process;
+
+    use rand::Rng;
+
+    use super::*;
+    use crate::rng::GameRng;
+
+    // aims everything at the closest monster, and always takes the extra HP
+    struct AimAtClosest;
+
+    impl Ui for AimAtClosest {
+        fn target_tile(
+            &mut self,
+            _: &Game,
+            objects: &[Object],
+            _: Option<f32>,
+        ) -> Option<(i32, i32)> {
+            objects[1..]
+                .iter()
+                .filter(|object| object.fighter.is_some())
+                .min_by_key(|object| objects[PLAYER].distance_to(object) as i32)
+                .map(Object::pos)
+        }
+
+        fn level_up_choice(&mut self, _: &Fighter) -> usize {
+            0
+        }
+    }
+
+    // a game played with a few hundred commands, random but for heading
+    // for the closest item and picking it up, to have something to throw
+    fn play(seed: u64) -> (Game, Vec<Object>) {
+        let (mut game, mut objects) = new_game(seed);
+        let mut choices = GameRng::new(0);
+        for _ in 0..300 {
+            if !objects[PLAYER].alive {
+                break;
+            }
+            let player = objects[PLAYER].pos();
+            let closest_item = objects
+                .iter()
+                .filter(|object| object.item.is_some())
+                .min_by_key(|object| objects[PLAYER].distance_to(object) as i32)
+                .map(Object::pos);
+            let command = match (closest_item, choices.gen_range(0, 10)) {
+                (Some(item), _) if item == player => Command::PickUp,
+                (Some((x, y)), 0..=4) => {
+                    Command::Move((x - player.0).signum(), (y - player.1).signum())
+                }
+                (_, 5) if !game.inventory.is_empty() => {
+                    Command::Throw(choices.gen_range(0, game.inventory.len()))
+                }
+                _ => Command::Move(choices.gen_range(-1, 2), choices.gen_range(-1, 2)),
+            };
+            play_command(command, &mut AimAtClosest, &mut game, &mut objects);
+        }
+        (game, objects)
+    }
+
+    fn saved(game: &Game, objects: &[Object]) -> String {
+        serde_json::to_string(&(game, objects)).unwrap()
+    }
+
+    #[test]
+    fn replays_the_same_game() {
+        let (game, objects) = play(3);
+        // answers to questions are part of it too
+        assert!(game
+            .inputs
+            .iter()
+            .any(|input| matches!(input, Input::Target(_))));
+        let replay = Replay {
+            seed: game.seed,
+            inputs: game.inputs.clone(),
+        };
+        let (replayed, replayed_objects) = run_headless(replay).unwrap();
+        assert_eq!(saved(&replayed, &replayed_objects), saved(&game, &objects));
+    }
+
+    #[test]
+    fn reads_back_what_was_written() {
+        let (game, _) = play(4);
+        let path = std::env::temp_dir().join(format!("replay-test-{}.jsonl", process::id()));
+        let path = path.to_str().unwrap();
+        ReplayWriter::create(path, &game).unwrap();
+        let replay = load_replay(path).unwrap();
+        fs::remove_file(path).unwrap();
+        assert_eq!(replay.seed, game.seed);
+        assert_eq!(replay.inputs, game.inputs);
+    }
+
+    #[test]
+    fn stops_where_the_replay_goes_wrong() {
+        let replay = Replay {
+            seed: 1,
+            inputs: vec![Input::Command(Command::Wait), Input::LevelUp(0)],
+        };
+        assert_eq!(
+            run_headless(replay).err(),
+            Some("replay out of sync at input 2: expected a command, found LevelUp(0)".into())
+        );
+    }
+}
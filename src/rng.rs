@@ -0,0 +1,42 @@
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+// The game's only source of randomness. Small enough to be saved with the game,
+// so the same seed and the same inputs always play out the same way.
+// (xorshift64* seeded through splitmix64)
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct GameRng {
+    state: u64,
+}
+
+impl GameRng {
+    pub fn new(seed: u64) -> Self {
+        // scramble the seed so that nearby seeds give unrelated streams, and
+        // make sure the state is never zero (xorshift would get stuck there)
+        let mut z = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^= z >> 31;
+        GameRng {
+            state: if z == 0 { 1 } else { z },
+        }
+    }
+}
+
+impl Rng for GameRng {
+    fn next_u32(&mut self) -> u32 {
+        (self.next_u64() >> 32) as u32
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+}
+
+// a seed for players who didn't ask for one
+pub fn random_seed() -> u64 {
+    rand::random()
+}
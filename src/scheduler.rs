@@ -0,0 +1,164 @@
+use crate::ai::ai_take_turn;
+use crate::object::Object;
+use crate::status::{tick_statuses, StatusKind};
+use crate::{Game, PLAYER};
+
+// how much energy something gathers each turn at normal speed; fast monsters
+// gather more, slow ones less
+pub const NORMAL_SPEED: i32 = 100;
+// the energy something needs to be able to act
+pub const TURN_ENERGY: i32 = 100;
+
+// what each kind of action costs. whatever is left over (or owed) carries on
+// to the next turn, so cheap actions add up to extra ones
+pub const MOVE_COST: i32 = 100;
+pub const ATTACK_COST: i32 = 100;
+pub const DRINK_COST: i32 = 50;
+pub const READ_COST: i32 = 150;
+pub const EQUIP_COST: i32 = 50;
+
+impl Object {
+    // the player and the monsters act; items, stairs and remains don't
+    pub fn acts(&self, id: usize) -> bool {
+        id == PLAYER || self.ai.is_some()
+    }
+
+    // how much energy it gathers each turn, with haste and slowness taken
+    // into account
+    pub fn speed(&self) -> i32 {
+        if self.has_status(StatusKind::Hasted) {
+            self.speed * 2
+        } else if self.has_status(StatusKind::Slowed) {
+            (self.speed / 2).max(1)
+        } else {
+            self.speed
+        }
+    }
+
+    pub fn spend_energy(&mut self, cost: i32) {
+        self.energy -= cost;
+    }
+}
+
+// once the player has acted, everything else does, and turns go by, until
+// it's the player's turn again (or they're dead). whoever has the most energy
+// goes first, the player on a tie, then the monsters in object order
+pub fn run_until_player_turn(game: &mut Game, objects: &mut [Object]) {
+    while objects[PLAYER].alive {
+        let next = objects
+            .iter()
+            .enumerate()
+            .filter(|&(id, object)| object.acts(id) && object.energy >= TURN_ENERGY)
+            .min_by_key(|&(id, object)| (-object.energy, id))
+            .map(|(id, _)| id);
+        match next {
+            Some(PLAYER) => break,
+            Some(id) => ai_take_turn(id, game, objects),
+            // nobody can act: time passes
+            None => pass_turn(game, objects),
+        }
+    }
+}
+
+// a turn goes by: every status effect takes hold once, and everything that's
+// still standing gathers energy as fast as it can
+fn pass_turn(game: &mut Game, objects: &mut [Object]) {
+    // a noise carries on for as many turns as the slowest of them takes to
+    // gather the energy for a turn, so everyone gets the chance to hear it
+    let cycle = objects
+        .iter()
+        .enumerate()
+        .filter(|&(id, object)| object.acts(id) && object.alive)
+        .map(|(_, object)| (TURN_ENERGY + object.speed() - 1) / object.speed())
+        .max()
+        .unwrap_or(1);
+    game.noises.retain_mut(|noise| {
+        noise.age += 1;
+        noise.age <= cycle
+    });
+    for id in 0..objects.len() {
+        if !objects[id].acts(id) {
+            continue;
+        }
+        tick_statuses(id, game, objects);
+        if objects[id].alive {
+            objects[id].energy += objects[id].speed();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::Ai;
+    use crate::map::{make_monster, Tile};
+    use crate::new_game;
+    use crate::{MAP_HEIGHT, MAP_WIDTH};
+
+    // the player at the west end of a long corridor, and an orc at the
+    // east end, coming for them one step at a time
+    fn corridor() -> (Game, Vec<Object>) {
+        let (mut game, mut objects) = new_game(1);
+        game.map = vec![vec![Tile::wall(); MAP_HEIGHT as usize]; MAP_WIDTH as usize];
+        for column in &mut game.map[1..=60] {
+            column[2] = Tile::empty();
+        }
+        objects.truncate(1);
+        objects[PLAYER].set_pos(2, 2);
+        let mut orc = make_monster("Orc", 50, 2);
+        orc.ai = Some(Ai::Hunting {
+            last_known: (2, 2),
+            fled: false,
+        });
+        objects.push(orc);
+        (game, objects)
+    }
+
+    // how far the orc comes while the player does something that costs
+    // `cost`, `times` times over
+    fn orc_steps(game: &mut Game, objects: &mut [Object], times: usize, cost: i32) -> i32 {
+        let start = objects[1].x;
+        for _ in 0..times {
+            objects[PLAYER].spend_energy(cost);
+            run_until_player_turn(game, objects);
+        }
+        start - objects[1].x
+    }
+
+    #[test]
+    fn everyone_acts_once_a_turn_at_normal_speed() {
+        let (mut game, mut objects) = corridor();
+        assert_eq!(orc_steps(&mut game, &mut objects, 10, MOVE_COST), 10);
+    }
+
+    #[test]
+    fn the_fast_act_more_often_and_the_slow_less() {
+        let (mut game, mut objects) = corridor();
+        objects[1].speed = NORMAL_SPEED * 2;
+        assert_eq!(orc_steps(&mut game, &mut objects, 10, MOVE_COST), 20);
+
+        let (mut game, mut objects) = corridor();
+        objects[1].speed = NORMAL_SPEED / 2;
+        assert_eq!(orc_steps(&mut game, &mut objects, 10, MOVE_COST), 5);
+    }
+
+    #[test]
+    fn haste_and_slowness_change_speed() {
+        let (mut game, mut objects) = corridor();
+        objects[1].add_status(StatusKind::Hasted, 20);
+        assert_eq!(orc_steps(&mut game, &mut objects, 10, MOVE_COST), 20);
+
+        let (mut game, mut objects) = corridor();
+        objects[1].add_status(StatusKind::Slowed, 20);
+        assert_eq!(orc_steps(&mut game, &mut objects, 10, MOVE_COST), 5);
+    }
+
+    #[test]
+    fn cheap_actions_add_up_to_extra_ones() {
+        let (mut game, mut objects) = corridor();
+        assert_eq!(orc_steps(&mut game, &mut objects, 10, DRINK_COST), 5);
+
+        let (mut game, mut objects) = corridor();
+        assert_eq!(orc_steps(&mut game, &mut objects, 10, READ_COST), 15);
+    }
+}
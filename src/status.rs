@@ -0,0 +1,288 @@
+use std::mem;
+
+use serde::{Deserialize, Serialize};
+
+use crate::colors::*;
+use crate::combat::DamageKind;
+use crate::object::Object;
+use crate::{Game, PLAYER};
+
+// what poison and fire take off every turn, and regeneration gives back
+const POISON_DAMAGE: i32 = 1;
+const BURNING_DAMAGE: i32 = 3;
+const REGENERATION_AMOUNT: i32 = 2;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StatusKind {
+    // hurts a little every turn
+    Poisoned,
+    // hurts a lot every turn
+    Burning,
+    // heals every turn
+    Regenerating,
+    // acts twice as often
+    Hasted,
+    // acts half as often
+    Slowed,
+    // can't act at all
+    Stunned,
+    // can only see right next to itself
+    Blinded,
+    // stumbles about at random
+    Confused,
+}
+
+// What happens when an effect is put on something that already has it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Stacking {
+    // the turns add up
+    Add,
+    // it lasts as long as the longer of the two
+    Refresh,
+}
+
+impl StatusKind {
+    pub fn name(self) -> &'static str {
+        use StatusKind::*;
+        match self {
+            Poisoned => "poisoned",
+            Burning => "burning",
+            Regenerating => "regenerating",
+            Hasted => "hasted",
+            Slowed => "slowed",
+            Stunned => "stunned",
+            Blinded => "blind",
+            Confused => "confused",
+        }
+    }
+
+    pub fn color(self) -> Color {
+        use StatusKind::*;
+        match self {
+            Poisoned => GREEN,
+            Burning => ORANGE,
+            Regenerating => LIGHT_VIOLET,
+            Hasted => LIGHT_BLUE,
+            Slowed => SKY,
+            Stunned => YELLOW,
+            Blinded => WHITE,
+            Confused => PINK,
+        }
+    }
+
+    fn stacking(self) -> Stacking {
+        use StatusKind::*;
+        match self {
+            // more poison is worse; everything else just starts over, so
+            // nothing can be kept stunned or confused for ever
+            Poisoned => Stacking::Add,
+            _ => Stacking::Refresh,
+        }
+    }
+
+    // the effect this one wears off straight away
+    fn cancels(self) -> Option<StatusKind> {
+        use StatusKind::*;
+        match self {
+            Hasted => Some(Slowed),
+            Slowed => Some(Hasted),
+            _ => None,
+        }
+    }
+}
+
+// An effect on a monster or the player, for a number of turns.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct StatusEffect {
+    pub kind: StatusKind,
+    pub turns: i32,
+}
+
+impl Object {
+    pub fn has_status(&self, kind: StatusKind) -> bool {
+        self.statuses.iter().any(|status| status.kind == kind)
+    }
+
+    pub fn status_turns(&self, kind: StatusKind) -> Option<i32> {
+        self.statuses
+            .iter()
+            .find(|status| status.kind == kind)
+            .map(|status| status.turns)
+    }
+
+    // put the effect on, following its rules for stacking with one that's
+    // already there
+    pub fn add_status(&mut self, kind: StatusKind, turns: i32) {
+        if let Some(cancelled) = kind.cancels() {
+            self.statuses.retain(|status| status.kind != cancelled);
+        }
+        match self.statuses.iter_mut().find(|status| status.kind == kind) {
+            Some(status) => match kind.stacking() {
+                Stacking::Add => status.turns += turns,
+                Stacking::Refresh => status.turns = status.turns.max(turns),
+            },
+            None => self.statuses.push(StatusEffect { kind, turns }),
+        }
+    }
+}
+
+// a turn's worth of every effect on the object: damage, healing, and one turn
+// less of each, with a message for the ones that wear off
+pub fn tick_statuses(id: usize, game: &mut Game, objects: &mut [Object]) {
+    let mut statuses = mem::take(&mut objects[id].statuses);
+    for status in &statuses {
+        match status.kind {
+            StatusKind::Poisoned => status_damage(id, POISON_DAMAGE, DamageKind::Poison, game, objects),
+            StatusKind::Burning => status_damage(id, BURNING_DAMAGE, DamageKind::Fire, game, objects),
+            StatusKind::Regenerating => objects[id].heal(REGENERATION_AMOUNT, game),
+            _ => {}
+        }
+    }
+    if !objects[id].alive {
+        return;
+    }
+
+    let visible = game.fov.is_in_fov(objects[id].x, objects[id].y);
+    for status in &mut statuses {
+        status.turns -= 1;
+        if status.turns > 0 {
+            continue;
+        }
+        if id == PLAYER {
+            game.messages
+                .add(format!("You are no longer {}.", status.kind.name()), YELLOW);
+        } else if visible {
+            game.messages.add(
+                format!(
+                    "The {} is no longer {}.",
+                    objects[id].name,
+                    status.kind.name()
+                ),
+                YELLOW,
+            );
+        }
+    }
+    statuses.retain(|status| status.turns > 0);
+    objects[id].statuses = statuses;
+}
+
+// poison and fire hurt like anything else, and the player gets the credit
+// for monsters they finish off, since they're the ones who set it going
+fn status_damage(
+    id: usize,
+    damage: i32,
+    kind: DamageKind,
+    game: &mut Game,
+    objects: &mut [Object],
+) {
+    if !objects[id].alive {
+        return;
+    }
+    let cause = kind.name();
+    let taken = objects[id].resisted(damage, kind, game);
+    if taken == 0 {
+        // shrugged off
+    } else if id == PLAYER {
+        game.messages
+            .add(format!("You take {} damage from the {}.", taken, cause), RED);
+    } else if game.fov.is_in_fov(objects[id].x, objects[id].y) {
+        game.messages.add(
+            format!(
+                "The {} takes {} damage from the {}.",
+                objects[id].name, taken, cause
+            ),
+            ORANGE,
+        );
+    }
+    if let Some(xp) = objects[id].take_damage(damage, kind, game) {
+        if id == PLAYER {
+            game.cause_of_death = Some(cause.into());
+        } else {
+            objects[PLAYER].fighter.as_mut().unwrap().xp += xp;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::make_monster;
+    use crate::new_game;
+
+    // the player and an orc, far enough apart to leave each other alone
+    fn player_and_orc() -> (Game, Vec<Object>) {
+        let (game, mut objects) = new_game(1);
+        objects.truncate(1);
+        objects.push(make_monster("Orc", 1, 1));
+        (game, objects)
+    }
+
+    #[test]
+    fn poison_adds_up() {
+        let (_, mut objects) = player_and_orc();
+        objects[1].add_status(StatusKind::Poisoned, 3);
+        objects[1].add_status(StatusKind::Poisoned, 4);
+        assert_eq!(objects[1].status_turns(StatusKind::Poisoned), Some(7));
+        assert_eq!(objects[1].statuses.len(), 1);
+    }
+
+    #[test]
+    fn everything_else_lasts_as_long_as_the_longer() {
+        let (_, mut objects) = player_and_orc();
+        objects[1].add_status(StatusKind::Stunned, 5);
+        objects[1].add_status(StatusKind::Stunned, 2);
+        assert_eq!(objects[1].status_turns(StatusKind::Stunned), Some(5));
+        objects[1].add_status(StatusKind::Stunned, 8);
+        assert_eq!(objects[1].status_turns(StatusKind::Stunned), Some(8));
+        assert_eq!(objects[1].statuses.len(), 1);
+    }
+
+    #[test]
+    fn haste_and_slowness_cancel_out() {
+        let (_, mut objects) = player_and_orc();
+        objects[1].add_status(StatusKind::Slowed, 5);
+        objects[1].add_status(StatusKind::Hasted, 5);
+        assert!(objects[1].has_status(StatusKind::Hasted));
+        assert!(!objects[1].has_status(StatusKind::Slowed));
+        objects[1].add_status(StatusKind::Slowed, 5);
+        assert!(!objects[1].has_status(StatusKind::Hasted));
+    }
+
+    #[test]
+    fn effects_hurt_every_turn_and_wear_off() {
+        let (mut game, mut objects) = player_and_orc();
+        let hp = objects[1].fighter.unwrap().hp;
+        objects[1].add_status(StatusKind::Poisoned, 2);
+        objects[1].add_status(StatusKind::Confused, 3);
+        tick_statuses(1, &mut game, &mut objects);
+        tick_statuses(1, &mut game, &mut objects);
+        assert_eq!(objects[1].fighter.unwrap().hp, hp - 2 * POISON_DAMAGE);
+        assert!(!objects[1].has_status(StatusKind::Poisoned));
+        assert_eq!(objects[1].status_turns(StatusKind::Confused), Some(1));
+        tick_statuses(1, &mut game, &mut objects);
+        assert!(objects[1].statuses.is_empty());
+        assert_eq!(objects[1].fighter.unwrap().hp, hp - 2 * POISON_DAMAGE);
+    }
+
+    #[test]
+    fn the_player_hears_when_an_effect_wears_off() {
+        let (mut game, mut objects) = player_and_orc();
+        objects[PLAYER].add_status(StatusKind::Hasted, 1);
+        tick_statuses(PLAYER, &mut game, &mut objects);
+        assert!(objects[PLAYER].statuses.is_empty());
+        let (last, _) = game.messages.iter().next_back().unwrap();
+        assert_eq!(last, "You are no longer hasted.");
+    }
+
+    #[test]
+    fn poison_kills_give_the_player_the_xp() {
+        let (mut game, mut objects) = player_and_orc();
+        let xp = objects[PLAYER].fighter.unwrap().xp;
+        objects[1].fighter.as_mut().unwrap().hp = POISON_DAMAGE;
+        objects[1].add_status(StatusKind::Poisoned, 5);
+        tick_statuses(1, &mut game, &mut objects);
+        assert!(!objects[1].alive);
+        assert!(objects[PLAYER].fighter.unwrap().xp > xp);
+    }
+}
@@ -0,0 +1,347 @@
+use crate::colors::*;
+use crate::dijkstra::DijkstraMap;
+use crate::map::{Map, Tile, MAP_HEIGHT, MAP_WIDTH};
+use crate::object::Object;
+use crate::path::step_cost;
+use crate::{Command, Game, PLAYER};
+
+// Where the player can ask to be walked to.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Destination {
+    // the nearest tile the player hasn't seen yet, again and again until
+    // there's nothing left
+    Unexplored,
+    // a tile the player has already seen
+    Tile(i32, i32),
+    // the way down, once it has been found
+    Stairs,
+}
+
+// The player walking somewhere on their own, a turn at a time, until they
+// get there or something happens that they would want to stop for.
+#[derive(Clone, Debug)]
+pub struct Travel {
+    destination: Destination,
+    // HP after the last step, to notice getting hurt
+    hp: i32,
+    steps: u32,
+}
+
+impl Travel {
+    // set off, unless there's already a reason not to
+    pub fn start(destination: Destination, game: &mut Game, objects: &[Object]) -> Option<Travel> {
+        if let Some(monster) = visible_monster(game, objects) {
+            game.messages.add(
+                format!("Not with the {} in view.", objects[monster].name),
+                WHITE,
+            );
+            return None;
+        }
+        if destination == Destination::Stairs && known_stairs(game, objects).is_none() {
+            game.messages
+                .add("You haven't found the stairs yet.", WHITE);
+            return None;
+        }
+        if let Destination::Tile(x, y) = destination {
+            if step_cost(&game.map, x, y).is_none() {
+                game.messages.add("You can't walk there.", WHITE);
+                return None;
+            }
+            let (player_x, player_y) = objects[PLAYER].pos();
+            let known_way = DijkstraMap::new(&known_map(game), &[(x, y)]).value(player_x, player_y);
+            if known_way.is_none() {
+                game.messages.add("You don't know a way there.", WHITE);
+                return None;
+            }
+        }
+        Some(Travel {
+            destination,
+            hp: player_hp(objects),
+            steps: 0,
+        })
+    }
+
+    // the command for the next step, or None once it's time to stop
+    pub fn next_command(&mut self, game: &mut Game, objects: &[Object]) -> Option<Command> {
+        let player = &objects[PLAYER];
+        if !player.alive {
+            return None;
+        }
+        if player_hp(objects) < self.hp {
+            game.messages.add("You stop: you're hurt!", LIGHT_RED);
+            return None;
+        }
+        if let Some(monster) = visible_monster(game, objects) {
+            game.messages.add(
+                format!("You stop: the {} comes into view.", objects[monster].name),
+                LIGHT_RED,
+            );
+            return None;
+        }
+        // exploring stops for items (but not whatever was already underfoot
+        // when setting off)
+        let item_underfoot = objects
+            .iter()
+            .find(|object| object.pos() == player.pos() && object.item.is_some());
+        if self.destination == Destination::Unexplored && self.steps > 0 {
+            if let Some(item) = item_underfoot {
+                game.messages
+                    .add(format!("You see a {} here.", item.name), WHITE);
+                return None;
+            }
+        }
+
+        let goals = match self.destination {
+            Destination::Unexplored => unexplored_tiles(game),
+            Destination::Tile(x, y) => vec![(x, y)],
+            Destination::Stairs => known_stairs(game, objects).into_iter().collect(),
+        };
+        if goals.contains(&player.pos()) {
+            // made it
+            return None;
+        }
+        let step = DijkstraMap::new(&known_map(game), &goals).step(player.pos(), objects);
+        match step {
+            // nothing to walk into, like a door that has been locked since
+            Some((dx, dy)) if step_cost(&game.map, player.x + dx, player.y + dy).is_none() => {
+                game.messages.add("You stop: the way is blocked.", WHITE);
+                None
+            }
+            Some((dx, dy)) => {
+                self.hp = player_hp(objects);
+                self.steps += 1;
+                Some(Command::Move(dx, dy))
+            }
+            None => {
+                let message = match self.destination {
+                    Destination::Unexplored => "There's nowhere left to explore.",
+                    _ => "There's no way through from here.",
+                };
+                game.messages.add(message, WHITE);
+                None
+            }
+        }
+    }
+}
+
+fn player_hp(objects: &[Object]) -> i32 {
+    objects[PLAYER].fighter.map_or(0, |f| f.hp)
+}
+
+// the first monster the player can see, if any
+fn visible_monster(game: &Game, objects: &[Object]) -> Option<usize> {
+    objects.iter().position(|object| {
+        object.ai.is_some() && object.alive && game.fov.is_in_fov(object.x, object.y)
+    })
+}
+
+// where the stairs are, if the player has seen them
+fn known_stairs(game: &Game, objects: &[Object]) -> Option<(i32, i32)> {
+    objects
+        .iter()
+        .find(|object| object.name == "stairs" && game.map[object.x as usize][object.y as usize].explored)
+        .map(Object::pos)
+}
+
+// the map as far as the player knows it: the way anywhere only goes through
+// tiles they have seen, whatever might be hidden in the rest
+fn known_map(game: &Game) -> Map {
+    let mut map = game.map.clone();
+    for tile in map.iter_mut().flatten() {
+        if !tile.explored {
+            *tile = Tile::wall();
+        }
+    }
+    map
+}
+
+// every tile the player could walk on but hasn't seen yet
+fn unexplored_tiles(game: &Game) -> Vec<(i32, i32)> {
+    (0..MAP_WIDTH)
+        .flat_map(|x| (0..MAP_HEIGHT).map(move |y| (x, y)))
+        .filter(|&(x, y)| {
+            !game.map[x as usize][y as usize].explored && step_cost(&game.map, x, y).is_some()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::{make_item, make_monster, Door};
+    use crate::object::Fighter;
+    use crate::{new_game, play_command, Ui};
+
+    // never asked anything while walking about
+    struct NoAnswers;
+
+    impl Ui for NoAnswers {
+        fn target_tile(&mut self, _: &Game, _: &[Object], _: Option<f32>) -> Option<(i32, i32)> {
+            None
+        }
+
+        fn level_up_choice(&mut self, _: &Fighter) -> usize {
+            0
+        }
+    }
+
+    fn dig(map: &mut Map, xs: impl Iterator<Item = i32> + Clone, ys: impl Iterator<Item = i32>) {
+        for y in ys {
+            for x in xs.clone() {
+                map[x as usize][y as usize] = Tile::empty();
+            }
+        }
+    }
+
+    // a small room at the west end, a door out of it to a straight corridor
+    // east to another small room, and a long way round to it to the south
+    fn two_ways() -> Map {
+        let mut map = vec![vec![Tile::wall(); MAP_HEIGHT as usize]; MAP_WIDTH as usize];
+        dig(&mut map, 1..=3, 1..=3);
+        dig(&mut map, 30..=32, 1..=3);
+        dig(&mut map, 5..=29, 2..=2);
+        map[4][2] = Tile::door(Door::Closed);
+        dig(&mut map, 2..=2, 4..=12);
+        dig(&mut map, 2..=31, 12..=12);
+        dig(&mut map, 31..=31, 4..=11);
+        map
+    }
+
+    // the player in the middle of the west room of `map`, on their own
+    fn game_on(map: Map) -> (Game, Vec<Object>) {
+        let (mut game, mut objects) = new_game(1);
+        objects.truncate(1);
+        objects[PLAYER].set_pos(2, 2);
+        game.map = map;
+        game.compute_fov(&objects[PLAYER]);
+        (game, objects)
+    }
+
+    // walk until the travel stops, and say why it did
+    fn walk(travel: &mut Travel, game: &mut Game, objects: &mut Vec<Object>) -> String {
+        while let Some(command) = travel.next_command(game, objects) {
+            play_command(command, &mut NoAnswers, game, objects);
+        }
+        game.messages.iter().last().unwrap().0.clone()
+    }
+
+    #[test]
+    fn explores_everything_there_is() {
+        let (mut game, mut objects) = game_on(two_ways());
+        let mut travel = Travel::start(Destination::Unexplored, &mut game, &objects).unwrap();
+        let stopped = walk(&mut travel, &mut game, &mut objects);
+        assert_eq!(stopped, "There's nowhere left to explore.");
+        assert!(unexplored_tiles(&game).is_empty());
+    }
+
+    #[test]
+    fn exploring_stops_for_monsters() {
+        let (mut game, mut objects) = game_on(two_ways());
+        objects.push(make_monster("Orc", 31, 2));
+        let mut travel = Travel::start(Destination::Unexplored, &mut game, &objects).unwrap();
+        let stopped = walk(&mut travel, &mut game, &mut objects);
+        assert_eq!(stopped, "You stop: the Orc comes into view.");
+        assert!(game.fov.is_in_fov(31, 2));
+        // and won't set off again while it's there
+        assert!(Travel::start(Destination::Unexplored, &mut game, &objects).is_none());
+    }
+
+    #[test]
+    fn exploring_stops_for_items() {
+        let (mut game, mut objects) = game_on(two_ways());
+        objects.push(make_item("Healing Potion", 2, 8));
+        let mut travel = Travel::start(Destination::Unexplored, &mut game, &objects).unwrap();
+        let stopped = walk(&mut travel, &mut game, &mut objects);
+        assert_eq!(stopped, "You see a Healing Potion here.");
+        assert_eq!(objects[PLAYER].pos(), (2, 8));
+    }
+
+    #[test]
+    fn exploring_stops_when_hurt() {
+        let (mut game, mut objects) = game_on(two_ways());
+        let mut travel = Travel::start(Destination::Unexplored, &mut game, &objects).unwrap();
+        let command = travel.next_command(&mut game, &objects).unwrap();
+        play_command(command, &mut NoAnswers, &mut game, &mut objects);
+        objects[PLAYER].fighter.as_mut().unwrap().hp -= 1;
+        assert!(travel.next_command(&mut game, &objects).is_none());
+        assert_eq!(
+            game.messages.iter().last().unwrap().0,
+            "You stop: you're hurt!"
+        );
+    }
+
+    // the long way round, and the room at the end of it, as if seen before
+    fn know_the_long_way(game: &mut Game) {
+        let known = [
+            (2..=2, 4..=12),
+            (2..=31, 12..=12),
+            (31..=31, 4..=11),
+            (30..=32, 1..=3),
+        ];
+        for (xs, ys) in known {
+            for x in xs {
+                for y in ys.clone() {
+                    game.map[x as usize][y as usize].explored = true;
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn travels_only_the_way_the_player_knows() {
+        let (mut game, mut objects) = game_on(two_ways());
+        know_the_long_way(&mut game);
+        let mut travel = Travel::start(Destination::Tile(31, 2), &mut game, &objects).unwrap();
+        let mut steps = 0;
+        while let Some(Command::Move(dx, dy)) = travel.next_command(&mut game, &objects) {
+            let (x, y) = (objects[PLAYER].x + dx, objects[PLAYER].y + dy);
+            assert!(
+                game.map[x as usize][y as usize].explored,
+                "into the unknown at ({}, {})",
+                x, y
+            );
+            play_command(
+                Command::Move(dx, dy),
+                &mut NoAnswers,
+                &mut game,
+                &mut objects,
+            );
+            steps += 1;
+        }
+        assert_eq!(objects[PLAYER].pos(), (31, 2));
+        assert!(steps > 40, "only {} steps", steps);
+    }
+
+    #[test]
+    fn takes_the_short_way_once_it_is_known() {
+        let (mut game, mut objects) = game_on(two_ways());
+        for tile in game.map.iter_mut().flatten() {
+            tile.explored = true;
+        }
+        let mut travel = Travel::start(Destination::Tile(31, 2), &mut game, &objects).unwrap();
+        walk(&mut travel, &mut game, &mut objects);
+        assert_eq!(objects[PLAYER].pos(), (31, 2));
+        // through the door, opening it on the way
+        assert_eq!(game.map[4][2].door, Some(Door::Open));
+    }
+
+    #[test]
+    fn wont_set_off_for_walls_or_places_it_cant_find() {
+        let (mut game, objects) = game_on(two_ways());
+        assert!(Travel::start(Destination::Tile(0, 0), &mut game, &objects).is_none());
+        assert_eq!(
+            game.messages.iter().last().unwrap().0,
+            "You can't walk there."
+        );
+        assert!(Travel::start(Destination::Tile(31, 2), &mut game, &objects).is_none());
+        assert_eq!(
+            game.messages.iter().last().unwrap().0,
+            "You don't know a way there."
+        );
+        assert!(Travel::start(Destination::Stairs, &mut game, &objects).is_none());
+        assert_eq!(
+            game.messages.iter().last().unwrap().0,
+            "You haven't found the stairs yet."
+        );
+    }
+}
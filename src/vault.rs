@@ -0,0 +1,360 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::sync::OnceLock;
+
+use crate::map::{self, Door, Transition, MAP_HEIGHT, MAP_WIDTH};
+use crate::raws::{
+    check_transitions, item_kind, load_raws, monster_kind, ITEMS_FILE, MONSTERS_FILE,
+};
+
+// where the hand-made rooms are kept, drawn out in plain text
+pub const VAULTS_FILE: &str = "vaults.txt";
+
+// What a character in a vault template stands for.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Glyph {
+    Wall,
+    Floor,
+    Door(Door),
+    // leave the dungeon as it is
+    Keep,
+    Monster(String),
+    Item(String),
+}
+
+impl Glyph {
+    // the glyphs every vault file has; monsters and items come from its
+    // `legend` lines
+    fn from_char(c: char) -> Option<Glyph> {
+        use Glyph::*;
+        let glyph = match c {
+            '#' => Wall,
+            '.' => Floor,
+            '+' => Door(map::Door::Closed),
+            '=' => Door(map::Door::Locked),
+            ' ' => Keep,
+            _ => return None,
+        };
+        Some(glyph)
+    }
+}
+
+// A hand-made room, stamped into the dungeon as it is drawn.
+#[derive(Debug)]
+pub struct Vault {
+    pub name: String,
+    // the percent chance of the vault turning up on each dungeon level
+    pub chance: Vec<Transition>,
+    // row by row, all the same length
+    pub rows: Vec<Vec<Glyph>>,
+}
+
+impl Vault {
+    pub fn width(&self) -> i32 {
+        self.rows[0].len() as i32
+    }
+
+    pub fn height(&self) -> i32 {
+        self.rows.len() as i32
+    }
+}
+
+static VAULTS: OnceLock<Result<Vec<Vault>, String>> = OnceLock::new();
+
+// the vaults in VAULTS_FILE, read the first time they are asked for. no file
+// just means no vaults, but a broken one is a mistake worth stopping for
+pub fn load_vaults() -> Result<&'static [Vault], String> {
+    let vaults = VAULTS.get_or_init(|| {
+        let text = match fs::read_to_string(VAULTS_FILE) {
+            Ok(text) => text,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(format!("{}: could not be read: {}", VAULTS_FILE, e)),
+        };
+        let vaults = parse_vaults(&text).map_err(|e| format!("{}: {}", VAULTS_FILE, e))?;
+        // the monsters and items in their legend have to be real ones
+        load_raws()?;
+        check_contents(&vaults).map_err(|e| format!("{}: {}", VAULTS_FILE, e))?;
+        Ok(vaults)
+    });
+    vaults.as_deref().map_err(Clone::clone)
+}
+
+// what the map builder asks for; by then main has already stopped on a broken
+// file
+pub fn vaults() -> &'static [Vault] {
+    load_vaults().unwrap_or_else(|e| panic!("{}", e))
+}
+
+// The format, one vault after another, separated by blank lines:
+//
+//   ; a comment
+//   legend <glyph> monster <name>
+//   legend <glyph> item <name>
+//   vault <name>
+//   chance <level>:<percent> <level>:<percent> ...
+//   <the rows of the room>
+pub fn parse_vaults(text: &str) -> Result<Vec<Vault>, String> {
+    let mut vaults: Vec<Vault> = vec![];
+    // the vault whose rows are being read, if any
+    let mut current: Option<Vault> = None;
+    // the monsters and items declared so far
+    let mut legend: HashMap<char, Glyph> = HashMap::new();
+
+    for (number, line) in text.lines().enumerate() {
+        let error = |problem: String| format!("line {}: {}", number + 1, problem);
+        let line = line.trim_end();
+        if line.starts_with(';') {
+            continue;
+        }
+        if line.is_empty() {
+            if let Some(vault) = current.take() {
+                vaults.push(finish_vault(vault).map_err(error)?);
+            }
+        } else if let Some(name) = line.strip_prefix("vault ") {
+            if let Some(vault) = current.take() {
+                vaults.push(finish_vault(vault).map_err(error)?);
+            }
+            current = Some(Vault {
+                name: name.trim().into(),
+                chance: vec![],
+                rows: vec![],
+            });
+        } else if let Some(entry) = line.strip_prefix("legend ") {
+            let (c, glyph) = parse_legend(entry).map_err(error)?;
+            if Glyph::from_char(c).is_some() || legend.contains_key(&c) {
+                return Err(error(format!("`{}` already stands for something", c)));
+            }
+            legend.insert(c, glyph);
+        } else if let Some(chances) = line.strip_prefix("chance ") {
+            let vault = current
+                .as_mut()
+                .ok_or_else(|| error("`chance` outside of a vault".into()))?;
+            for chance in chances.split_whitespace() {
+                vault.chance.push(parse_chance(chance).map_err(error)?);
+            }
+        } else {
+            let vault = current
+                .as_mut()
+                .ok_or_else(|| error(format!("`{}` outside of a vault", line)))?;
+            let row = line
+                .chars()
+                .map(|c| {
+                    Glyph::from_char(c)
+                        .or_else(|| legend.get(&c).cloned())
+                        .ok_or_else(|| error(format!("unknown glyph `{}`", c)))
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            vault.rows.push(row);
+        }
+    }
+    if let Some(vault) = current {
+        vaults.push(finish_vault(vault).map_err(|e| format!("end of file: {}", e))?);
+    }
+    Ok(vaults)
+}
+
+// a `<glyph> monster <name>` or `<glyph> item <name>` legend entry
+fn parse_legend(entry: &str) -> Result<(char, Glyph), String> {
+    let invalid = || {
+        format!(
+            "`legend {}` should look like legend <glyph> monster|item <name>",
+            entry
+        )
+    };
+    let mut words = entry.splitn(3, ' ');
+    let (glyph, kind, name) = match (words.next(), words.next(), words.next()) {
+        (Some(glyph), Some(kind), Some(name)) if !name.trim().is_empty() => {
+            (glyph, kind, name.trim())
+        }
+        _ => return Err(invalid()),
+    };
+    let mut chars = glyph.chars();
+    let c = match (chars.next(), chars.next()) {
+        (Some(c), None) => c,
+        _ => return Err(invalid()),
+    };
+    match kind {
+        "monster" => Ok((c, Glyph::Monster(name.into()))),
+        "item" => Ok((c, Glyph::Item(name.into()))),
+        _ => Err(invalid()),
+    }
+}
+
+// a `<level>:<percent>` pair
+fn parse_chance(chance: &str) -> Result<Transition, String> {
+    let invalid = || format!("`{}` should look like <level>:<percent>", chance);
+    let (level, value) = chance.split_once(':').ok_or_else(invalid)?;
+    Ok(Transition {
+        level: level.parse().map_err(|_| invalid())?,
+        value: value.parse().map_err(|_| invalid())?,
+    })
+}
+
+// check a vault that has been read in full, padding short rows with `Keep`
+fn finish_vault(mut vault: Vault) -> Result<Vault, String> {
+    if vault.rows.is_empty() {
+        return Err(format!("vault `{}` has no rows", vault.name));
+    }
+    let width = vault.rows.iter().map(|row| row.len()).max().unwrap_or(0);
+    for row in &mut vault.rows {
+        row.resize(width, Glyph::Keep);
+    }
+    // leave room for the wall around the edge of the map, and a way around
+    // the vault inside it
+    if vault.width() > MAP_WIDTH - 4 || vault.height() > MAP_HEIGHT - 4 {
+        return Err(format!("vault `{}` is bigger than the map", vault.name));
+    }
+    check_transitions(&vault.chance)
+        .map_err(|e| format!("vault `{}`: `chance` {}", vault.name, e))?;
+    Ok(vault)
+}
+
+// every monster and item in the vaults is in MONSTERS_FILE or ITEMS_FILE,
+// which have to be loaded first
+fn check_contents(vaults: &[Vault]) -> Result<(), String> {
+    for vault in vaults {
+        for glyph in vault.rows.iter().flatten() {
+            let missing = match glyph {
+                Glyph::Monster(name) if monster_kind(name).is_none() => {
+                    format!("no monster called `{}` in {}", name, MONSTERS_FILE)
+                }
+                Glyph::Item(name) if item_kind(name).is_none() => {
+                    format!("no item called `{}` in {}", name, ITEMS_FILE)
+                }
+                _ => continue,
+            };
+            return Err(format!("vault `{}`: there's {}", vault.name, missing));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VAULTS: &str = "\
+; a comment
+legend / item Sword
+legend [ item Shield
+legend T monster Troll
+
+vault Armoury
+chance 2:5 4:10
+#####
+#/.[#
+##+
+
+vault Den
+chance 1:20
+ ###
+ #T=
+";
+
+    #[test]
+    fn parses_vaults() {
+        let vaults = parse_vaults(VAULTS).unwrap();
+        assert_eq!(vaults.len(), 2);
+        let armoury = &vaults[0];
+        assert_eq!(armoury.name, "Armoury");
+        assert_eq!(
+            armoury
+                .chance
+                .iter()
+                .map(|t| (t.level, t.value))
+                .collect::<Vec<_>>(),
+            [(2, 5), (4, 10)]
+        );
+        assert_eq!((armoury.width(), armoury.height()), (5, 3));
+        use Glyph::*;
+        assert_eq!(
+            armoury.rows[1],
+            [
+                Wall,
+                Item("Sword".into()),
+                Floor,
+                Item("Shield".into()),
+                Wall
+            ]
+        );
+        // short rows are filled out with rock left alone
+        assert_eq!(
+            armoury.rows[2],
+            [Wall, Wall, Door(map::Door::Closed), Keep, Keep]
+        );
+        let den = &vaults[1];
+        assert_eq!(
+            den.rows[1],
+            [Keep, Wall, Monster("Troll".into()), Door(map::Door::Locked)]
+        );
+    }
+
+    #[test]
+    fn no_vaults_is_fine() {
+        assert!(parse_vaults("").unwrap().is_empty());
+        assert!(parse_vaults("; nothing here yet\n").unwrap().is_empty());
+    }
+
+    #[test]
+    fn rejects_broken_vaults() {
+        let too_wide = format!("vault Hall\n{}\n", "#".repeat(MAP_WIDTH as usize));
+        let cases = [
+            ("chance 1:10\n", "line 1: `chance` outside of a vault"),
+            ("###\n", "line 1: `###` outside of a vault"),
+            ("vault Pit\n#x#\n", "line 2: unknown glyph `x`"),
+            (
+                "vault Pit\n#x#\nlegend x item Sword\n",
+                "line 2: unknown glyph `x`",
+            ),
+            (
+                "legend x monster\n",
+                "line 1: `legend x monster` should look like legend <glyph> monster|item <name>",
+            ),
+            (
+                "legend xy item Sword\n",
+                "line 1: `legend xy item Sword` should look like legend <glyph> monster|item <name>",
+            ),
+            (
+                "legend x thing Sword\n",
+                "line 1: `legend x thing Sword` should look like legend <glyph> monster|item <name>",
+            ),
+            (
+                "legend x item Sword\nlegend x item Shield\n",
+                "line 2: `x` already stands for something",
+            ),
+            (
+                "legend # item Sword\n",
+                "line 1: `#` already stands for something",
+            ),
+            (
+                "vault Pit\nchance 1-10\n",
+                "line 2: `1-10` should look like <level>:<percent>",
+            ),
+            (
+                "vault Pit\nchance 1:ten\n",
+                "line 2: `1:ten` should look like <level>:<percent>",
+            ),
+            ("vault Pit\n\n", "line 2: vault `Pit` has no rows"),
+            (
+                "vault Pit\nchance 4:10 2:20\n#\n\n",
+                "line 4: vault `Pit`: `chance` levels should go up, one entry each",
+            ),
+            (
+                "vault Pit\nvault Hole\n#\n",
+                "line 2: vault `Pit` has no rows",
+            ),
+            (
+                "vault Pit\nchance 1:10\n",
+                "end of file: vault `Pit` has no rows",
+            ),
+            (
+                &too_wide,
+                "end of file: vault `Hall` is bigger than the map",
+            ),
+        ];
+        for (text, error) in cases {
+            assert_eq!(parse_vaults(text).unwrap_err(), error);
+        }
+    }
+}